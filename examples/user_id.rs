@@ -66,6 +66,10 @@ async fn main() {
             };
             c
         }
+        SessionType::HumanVerificationRequired(c) => {
+            eprintln!("Human verification required: {:?}", c.methods());
+            return;
+        }
     };
 
     let user = session.get_user().do_async(&client).await.unwrap();
@@ -61,6 +61,10 @@ fn main() {
             };
             c
         }
+        SessionType::HumanVerificationRequired(c) => {
+            eprintln!("Human verification required: {:?}", c.methods());
+            return;
+        }
     };
 
     let user = session.get_user().do_sync(&client).unwrap();
@@ -2,7 +2,12 @@ package main
 
 /*
 #include <stdint.h>
+
+#define SRP_ERROR_OTHER 1
+#define SRP_ERROR_MODULUS_SIGNATURE 2
+
 typedef struct{
+	int error_kind;
 	unsigned char* client_proof;
 	size_t client_proof_len;
 	unsigned char* client_ephemeral;
@@ -18,6 +23,7 @@ import "C"
 
 import (
 	"fmt"
+	"strings"
 	"unsafe"
 
 	"github.com/ProtonMail/go-srp"
@@ -27,11 +33,13 @@ import (
 func SRPAuth(username string, password []byte, version int, salt string, modulus string, serverEphemeral string, result *C.SRPAuthResult) *C.char {
 	srpAuth, err := srp.NewAuth(version, username, password, salt, modulus, serverEphemeral)
 	if err != nil {
+		result.error_kind = modulusErrorKind(err)
 		return C.CString(fmt.Sprintf("%v", err))
 	}
 
 	proofs, err := srpAuth.GenerateProofs(2048)
 	if err != nil {
+		result.error_kind = C.SRP_ERROR_OTHER
 		return C.CString(fmt.Sprintf("%v", err))
 	}
 
@@ -54,6 +62,16 @@ func CGoFree(ptr *C.void) {
     C.free(unsafe.Pointer(ptr))
 }
 
+// go-srp does not expose typed errors, the clear signed modulus checks are recognized by their
+// message instead.
+func modulusErrorKind(err error) C.int {
+	msg := err.Error()
+	if strings.Contains(msg, "modulus signature") || strings.Contains(msg, "after modulus") {
+		return C.SRP_ERROR_MODULUS_SIGNATURE
+	}
+	return C.SRP_ERROR_OTHER
+}
+
 func sliceToCMem(slice []byte) (*C.uchar, C.size_t) {
 	cBuf := C.CBytes(slice)
 	return (*C.uchar)(cBuf), C.size_t(len(slice))
@@ -14,8 +14,30 @@ use crate::go::*;
 
 use base64::Engine;
 use std::ffi::c_void;
+use std::fmt::{Display, Formatter};
 use std::mem::MaybeUninit;
 
+/// Reasons why [`SRPAuth::generate`] can fail.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SRPError {
+    /// The signature of the modulus could not be verified against the server key. This
+    /// indicates the modulus was tampered with and the login should not proceed.
+    ModulusSignature(String),
+    /// Any other failure while computing the proofs.
+    Proof(String),
+}
+
+impl Display for SRPError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SRPError::ModulusSignature(e) => write!(f, "Invalid modulus signature: {e}"),
+            SRPError::Proof(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SRPError {}
+
 /// Client SRP Auth information.
 pub struct SRPAuth {
     pub client_proof: String,
@@ -40,7 +62,7 @@ impl SRPAuth {
         salt: &str,
         modulus: &str,
         server_ephemeral: &str,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, SRPError> {
         let username = SafeGoString::new(username);
         let modulus = SafeGoString::new(modulus);
         let server_ephemeral = SafeGoString::new(server_ephemeral);
@@ -64,7 +86,12 @@ impl SRPAuth {
             );
 
             if !error.is_null() {
-                return Err(OwnedCStr::new(error).to_string());
+                let msg = OwnedCStr::new(error).to_string();
+                return Err(if result.error_kind == SRP_ERROR_MODULUS_SIGNATURE as i32 {
+                    SRPError::ModulusSignature(msg)
+                } else {
+                    SRPError::Proof(msg)
+                });
             }
 
             let client_proof = CBytes::new(result.client_proof, result.client_proof_len);
@@ -112,3 +139,26 @@ fn test_srp_call() {
     )
     .unwrap();
 }
+
+#[test]
+fn test_srp_tampered_modulus() {
+    let version = 4;
+    let username = "Cyb3rReaper";
+    let password = "123";
+    let salt = "CGhrAMJla9YHGQ==";
+    // Same modulus as above with the first character changed, the signature no longer matches.
+    let signed_modulus = "-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA256\n\np4ycZ14/7LfHkuSKWNlpQEh6bwLMVKvo0MFqVq9wHXwkZ/zMcqYaVhqNvLyDB0WY5Uv/Bo23JQsox52lM+4jPydw9/A9saAj8erLCc3ZaZHxOl/a8tlYTq7FeDrbhSSgivwTKJ5Y9otla/U8FATZBxqi7nqDihS5/7x/yK3VRnEsBG1i5DcY1UQK3KD9i9v7N2QTuGFYnRCv0MFsHzrQZWvUa1NsUhozU5PSV5s7hZkb/p6J3B9ybD6+LzuLS9fyLMcVdxzn2WUXG7JLeBbqsoECUfq9KP2waTzVLELOenWUV1wbioceJsaiP97ViwNJdnKx1ICoYu2c+z8ctVcqlw==\n-----BEGIN PGP SIGNATURE-----\nVersion: ProtonMail\nComment: https://protonmail.com\n\nwl4EARYIABAFAlwB1j0JEDUFhcTpUY8mAAB02wD5AOhMNS/K6/nvaeRhTr5n\niDGMalQccYlb58XzUEhqf3sBAOcTsz0fP3PVdMQYBbqcBl9Y6LGIG9DF4B4H\nZeLCoyYN\n=cAxM\n-----END PGP SIGNATURE-----\n";
+    let server_ephemeral = "vl0zIXo4bLPtYVoy3kIvhWQx3ObPMYTY0c5/TFHlmwgBW6Hz/p2XDJdDykF3rBfwrSUD4tfs1YRCfgGfvxegCIQhL419OPYgA+ApXUuS2ni86AXUfjPnvJju/inYQxER8nzEhM8DZYAiNM44qeepmXGrHmwjXAMzyaggqxmkTq4v+seKntFE5oH7iIFacgP52wnV/p6OLOMNS4t/vZ3haKaoEVoFyCVVoTJ/OVPp1ZoUovOoxwDvUAOjSEgswenR96xT+4CsPz9Dm+yF/bDugcWGQ4KB8KEzBrO0PqmCQWMYOKaILegtgTjg08eQTvGylSEZmbTeVzoPe/THqh2bJw==";
+
+    let err = SRPAuth::generate(
+        username,
+        password,
+        version,
+        salt,
+        signed_modulus,
+        server_ephemeral,
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(err, SRPError::ModulusSignature(_)));
+}
@@ -1,5 +1,6 @@
+use crate::domain::HumanVerificationType;
 use crate::http::{Error, RequestDesc, Sequence};
-use crate::requests::{CaptchaRequest, Ping};
+use crate::requests::{CaptchaRequest, Ping, RequestHumanVerificationCodeRequest};
 
 pub fn ping() -> impl Sequence<Output = (), Error = Error> {
     Ping.to_request()
@@ -8,3 +9,36 @@ pub fn ping() -> impl Sequence<Output = (), Error = Error> {
 pub fn captcha_get(token: &str, force_web: bool) -> impl Sequence<Output = String, Error = Error> {
     CaptchaRequest::new(token, force_web).to_request()
 }
+
+/// Send a human verification code to `destination`. Only [`HumanVerificationType::Email`] and
+/// [`HumanVerificationType::Sms`] send codes, other types fail with [`Error::Request`].
+pub fn human_verification_code_request(
+    hv_type: HumanVerificationType,
+    destination: &str,
+) -> Result<impl Sequence<Output = (), Error = Error>, Error> {
+    let request = match hv_type {
+        HumanVerificationType::Email => RequestHumanVerificationCodeRequest::new_email(destination),
+        HumanVerificationType::Sms => RequestHumanVerificationCodeRequest::new_sms(destination),
+        HumanVerificationType::Captcha => {
+            return Err(Error::Request(anyhow::anyhow!(
+                "Human verification type '{hv_type}' does not send a code"
+            )))
+        }
+    };
+    Ok(request.to_request())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn human_verification_code_request_rejects_captcha() {
+        assert!(human_verification_code_request(HumanVerificationType::Email, "a@b.c").is_ok());
+        assert!(human_verification_code_request(HumanVerificationType::Sms, "+123").is_ok());
+        assert!(matches!(
+            human_verification_code_request(HumanVerificationType::Captcha, "a@b.c"),
+            Err(Error::Request(_))
+        ));
+    }
+}
@@ -0,0 +1,276 @@
+//! Events pushed by the server over a websocket, see [`event_stream`].
+
+use crate::clientv2::Session;
+use crate::domain::{Event, EventId, MoreEvents};
+use crate::http;
+use crate::http::{ClientAsync, Sequence};
+use futures_util::{Stream, StreamExt};
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// How long the server may hold a poll request while the websocket is unavailable.
+const POLL_WAIT: Duration = Duration::from_secs(30);
+/// Minimum time between two polls, for servers which answer poll requests immediately.
+const MIN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Minimum time between two connection attempts to the websocket.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+/// Delay before retrying after a failed request, doubled by every further failure.
+const MIN_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound of the delay before retrying after a failed request.
+const MAX_ERROR_BACKOFF: Duration = Duration::from_secs(60);
+
+type Socket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Stream the events of `session` which follow the event `from`.
+///
+/// The websocket at `url` is authenticated with the UID and access token of the session. Its
+/// messages only signal that new events are available, the events themselves are fetched with
+/// [`Session::get_event`] so the cursor is handled the same way as when polling. While the
+/// websocket can't be reached the stream falls back to [`Session::get_event_longpoll`] and
+/// periodically tries to reconnect.
+///
+/// Errors of the event requests are yielded without ending the stream, the next item resumes
+/// from the same cursor after a delay which grows with every consecutive error. Errors which
+/// retrying can't fix, such as an expired session or a rejected request, end the stream once
+/// they have been yielded.
+pub fn event_stream<'a, T: ClientAsync>(
+    session: &'a Session,
+    client: &'a T,
+    url: &str,
+    from: EventId,
+) -> impl Stream<Item = Result<Event, http::Error>> + 'a {
+    let state = EventStreamState {
+        session,
+        client,
+        url: url.to_string(),
+        cursor: from,
+        more: false,
+        socket: None,
+        last_connect: None,
+        backoff: None,
+        finished: false,
+    };
+
+    futures_util::stream::unfold(state, |mut state| async move {
+        let event = state.next_item().await?;
+        Some((event, state))
+    })
+}
+
+struct EventStreamState<'a, T> {
+    session: &'a Session,
+    client: &'a T,
+    url: String,
+    cursor: EventId,
+    /// The last event reported that more events are available right away.
+    more: bool,
+    socket: Option<Socket>,
+    last_connect: Option<Instant>,
+    /// Delay before the next attempt, set while requests keep failing.
+    backoff: Option<Duration>,
+    /// A terminal error was yielded.
+    finished: bool,
+}
+
+impl<'a, T: ClientAsync> EventStreamState<'a, T> {
+    async fn next_item(&mut self) -> Option<Result<Event, http::Error>> {
+        if self.finished {
+            return None;
+        }
+
+        if let Some(delay) = self.backoff {
+            self.client.sleep(delay).await;
+        }
+
+        let event = self.next_event().await;
+        match &event {
+            Ok(_) => self.backoff = None,
+            Err(e) if is_terminal_error(e) => {
+                log::debug!("Ending event stream: {e}");
+                self.finished = true;
+            }
+            Err(e) => {
+                let backoff = next_error_backoff(self.backoff, e);
+                log::debug!("Event request failed, retrying in {backoff:?}: {e}");
+                self.backoff = Some(backoff);
+            }
+        }
+
+        Some(event)
+    }
+
+    async fn next_event(&mut self) -> Result<Event, http::Error> {
+        loop {
+            if !self.more {
+                self.connect_if_due().await;
+                if self.socket.is_none() {
+                    if let Some(event) = self.poll().await? {
+                        return Ok(self.advance(event));
+                    }
+                    continue;
+                }
+
+                if !self.wait_for_notification().await {
+                    continue;
+                }
+            }
+
+            let event = self
+                .session
+                .get_event(&self.cursor)
+                .do_async(self.client)
+                .await?;
+            if event.has_changes_since(&self.cursor) {
+                return Ok(self.advance(event));
+            }
+            self.more = false;
+        }
+    }
+
+    fn advance(&mut self, event: Event) -> Event {
+        self.cursor = event.event_id.clone();
+        self.more = event.more == MoreEvents::Yes;
+        event
+    }
+
+    async fn poll(&mut self) -> Result<Option<Event>, http::Error> {
+        let started = Instant::now();
+        let event = self
+            .session
+            .get_event_longpoll(&self.cursor, POLL_WAIT)
+            .do_async(self.client)
+            .await?;
+
+        if event.is_none() {
+            if let Some(remaining) = MIN_POLL_INTERVAL.checked_sub(started.elapsed()) {
+                self.client.sleep(remaining).await;
+            }
+        }
+
+        Ok(event)
+    }
+
+    async fn connect_if_due(&mut self) {
+        if self.socket.is_some()
+            || self
+                .last_connect
+                .is_some_and(|t| t.elapsed() < RECONNECT_INTERVAL)
+        {
+            return;
+        }
+
+        self.last_connect = Some(Instant::now());
+        match self.connect().await {
+            Ok(socket) => {
+                log::debug!("Connected to event websocket");
+                self.socket = Some(socket);
+            }
+            Err(e) => log::debug!("Event websocket unavailable, polling instead: {e}"),
+        }
+    }
+
+    async fn connect(&self) -> Result<Socket, tungstenite::Error> {
+        let (uid, access_token) = self.session.credentials();
+        let mut request = self.url.as_str().into_client_request()?;
+        let headers = request.headers_mut();
+        headers.insert(http::X_PM_UID_HEADER, HeaderValue::from_str(&uid)?);
+        headers.insert(
+            "authorization",
+            HeaderValue::from_str(&format!("Bearer {access_token}"))?,
+        );
+
+        let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+        Ok(socket)
+    }
+
+    /// Wait until the websocket signals that new events are available. Returns false if the
+    /// websocket disconnected in the meantime.
+    async fn wait_for_notification(&mut self) -> bool {
+        let Some(socket) = self.socket.as_mut() else {
+            return false;
+        };
+
+        loop {
+            match socket.next().await {
+                Some(Ok(Message::Text(_) | Message::Binary(_))) => return true,
+                Some(Ok(Message::Close(_))) | None => {
+                    log::debug!("Event websocket closed, polling instead");
+                    break;
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    log::debug!("Event websocket failed, polling instead: {e}");
+                    break;
+                }
+            }
+        }
+
+        self.socket = None;
+        false
+    }
+}
+
+/// Whether retrying can't recover from `e`, e.g. the session expired or the server rejected the
+/// request.
+fn is_terminal_error(e: &http::Error) -> bool {
+    match e {
+        http::Error::API(e) => !e.is_retryable(),
+        http::Error::AppVersionTooOld { .. }
+        | http::Error::ScopeDowngraded { .. }
+        | http::Error::InsufficientScope { .. }
+        | http::Error::SessionExpired(_) => true,
+        _ => false,
+    }
+}
+
+/// Double `previous`, within the backoff bounds, or wait longer if the server asked to.
+fn next_error_backoff(previous: Option<Duration>, e: &http::Error) -> Duration {
+    let backoff = previous.map_or(MIN_ERROR_BACKOFF, |d| (d * 2).min(MAX_ERROR_BACKOFF));
+    match e {
+        http::Error::API(e) => e.retry_after.map_or(backoff, |r| r.max(backoff)),
+        _ => backoff,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::requests::APIError;
+
+    #[test]
+    fn event_stream_terminal_errors() {
+        assert!(is_terminal_error(&http::Error::SessionExpired(
+            APIError::new(422)
+        )));
+        assert!(is_terminal_error(&http::Error::API(APIError::new(422))));
+        assert!(!is_terminal_error(&http::Error::API(APIError::new(503))));
+        assert!(!is_terminal_error(&http::Error::Connection(
+            anyhow::anyhow!("failed")
+        )));
+    }
+
+    #[test]
+    fn event_stream_error_backoff() {
+        let err = http::Error::Connection(anyhow::anyhow!("failed"));
+        assert_eq!(next_error_backoff(None, &err), MIN_ERROR_BACKOFF);
+        assert_eq!(
+            next_error_backoff(Some(MIN_ERROR_BACKOFF), &err),
+            MIN_ERROR_BACKOFF * 2
+        );
+        assert_eq!(
+            next_error_backoff(Some(MAX_ERROR_BACKOFF), &err),
+            MAX_ERROR_BACKOFF
+        );
+
+        let mut api_err = APIError::new(429);
+        api_err.retry_after = Some(Duration::from_secs(120));
+        assert_eq!(
+            next_error_backoff(None, &http::Error::API(api_err)),
+            Duration::from_secs(120)
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use crate::clientv2::{LoginError, Session, SessionType};
+use crate::domain::{
+    HumanVerification, HumanVerificationLoginData, HumanVerificationType, SecretString,
+};
+use crate::http::Sequence;
+
+/// Login which the server only accepts once the user passed a human verification. Complete one
+/// of the [`HvChallenge::methods`] and submit its result to resume the login.
+#[derive(Debug)]
+pub struct HvChallenge {
+    pub(super) verification: HumanVerification,
+    pub(super) username: String,
+    pub(super) password: SecretString,
+}
+
+impl HvChallenge {
+    /// Verification methods accepted by the server.
+    pub fn methods(&self) -> &[HumanVerificationType] {
+        &self.verification.methods
+    }
+
+    /// Token of the challenge, e.g. to fetch the captcha with [`crate::captcha_get`] or to send
+    /// a code with [`crate::human_verification_code_request`].
+    pub fn token(&self) -> &str {
+        &self.verification.token
+    }
+
+    /// Resume the login with the token of the solved captcha, see
+    /// [`HVCaptchaMessage`](crate::domain::HVCaptchaMessage).
+    pub fn submit_captcha(
+        &self,
+        token: &str,
+    ) -> impl Sequence<Output = SessionType, Error = LoginError> + '_ {
+        self.resume(HumanVerificationLoginData {
+            hv_type: HumanVerificationType::Captcha,
+            token: token.to_string(),
+        })
+    }
+
+    /// Resume the login with the `code` which was sent by email to `destination`.
+    pub fn submit_email_code(
+        &self,
+        destination: &str,
+        code: &str,
+    ) -> impl Sequence<Output = SessionType, Error = LoginError> + '_ {
+        self.resume(HumanVerificationLoginData::from_code(
+            HumanVerificationType::Email,
+            destination,
+            code,
+        ))
+    }
+
+    /// Resume the login with the `code` which was sent by sms to `destination`.
+    pub fn submit_sms_code(
+        &self,
+        destination: &str,
+        code: &str,
+    ) -> impl Sequence<Output = SessionType, Error = LoginError> + '_ {
+        self.resume(HumanVerificationLoginData::from_code(
+            HumanVerificationType::Sms,
+            destination,
+            code,
+        ))
+    }
+
+    fn resume(
+        &self,
+        hv: HumanVerificationLoginData,
+    ) -> impl Sequence<Output = SessionType, Error = LoginError> + '_ {
+        Session::login(&self.username, &self.password, Some(hv))
+    }
+}
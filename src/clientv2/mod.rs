@@ -1,7 +1,17 @@
 mod client;
+#[cfg(feature = "events-ws")]
+pub mod events_ws;
+mod human_verification;
+mod pages;
+mod scoped;
 mod session;
+mod srp;
 mod totp;
 
 pub use client::*;
+pub use human_verification::*;
+pub use pages::*;
+pub use scoped::*;
 pub use session::*;
+pub use srp::*;
 pub use totp::*;
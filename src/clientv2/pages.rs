@@ -0,0 +1,183 @@
+//! Listings which span several pages, see [`Session::iter_messages`].
+
+use crate::clientv2::Session;
+use crate::domain::{Message, MessageFilter};
+use crate::http;
+use crate::http::{ClientSync, Sequence};
+use crate::requests::{GetMessagesRequest, GetMessagesResponse};
+use std::collections::VecDeque;
+
+impl Session {
+    /// Iterate over all the messages matching `filter`, starting at its page. Further pages are
+    /// requested as the iterator advances, until a page is shorter than the page size or the
+    /// total reported by the server is reached.
+    ///
+    /// The iterator ends after yielding a request error.
+    pub fn iter_messages<'a, T: ClientSync>(
+        &'a self,
+        client: &'a T,
+        filter: MessageFilter,
+    ) -> MessagesIter<'a, T> {
+        MessagesIter {
+            session: self,
+            client,
+            pages: MessagePages::new(filter),
+        }
+    }
+
+    /// Async version of [`Session::iter_messages`].
+    #[cfg(feature = "stream")]
+    pub fn stream_messages<'a, T: http::ClientAsync>(
+        &'a self,
+        client: &'a T,
+        filter: MessageFilter,
+    ) -> impl futures_util::Stream<Item = Result<Message, http::Error>> + 'a {
+        let state = (self, client, MessagePages::new(filter));
+        futures_util::stream::unfold(state, |(session, client, mut pages)| async move {
+            loop {
+                if let Some(message) = pages.messages.pop_front() {
+                    return Some((Ok(message), (session, client, pages)));
+                }
+                if pages.done {
+                    return None;
+                }
+
+                let page = session
+                    .request(GetMessagesRequest::new(&pages.filter))
+                    .do_async(client)
+                    .await;
+                match page {
+                    Ok(page) => pages.push_page(page),
+                    Err(e) => {
+                        pages.done = true;
+                        return Some((Err(e), (session, client, pages)));
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Iterator over all the messages of a listing, see [`Session::iter_messages`].
+pub struct MessagesIter<'a, T> {
+    session: &'a Session,
+    client: &'a T,
+    pages: MessagePages,
+}
+
+impl<'a, T: ClientSync> Iterator for MessagesIter<'a, T> {
+    type Item = Result<Message, http::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(message) = self.pages.messages.pop_front() {
+                return Some(Ok(message));
+            }
+            if self.pages.done {
+                return None;
+            }
+
+            let page = self
+                .session
+                .request(GetMessagesRequest::new(&self.pages.filter))
+                .do_sync(self.client);
+            match page {
+                Ok(page) => self.pages.push_page(page),
+                Err(e) => {
+                    self.pages.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Messages of the last page which were not yielded yet and the filter for the next page.
+struct MessagePages {
+    filter: MessageFilter,
+    messages: VecDeque<Message>,
+    fetched: usize,
+    done: bool,
+}
+
+impl MessagePages {
+    fn new(filter: MessageFilter) -> Self {
+        Self {
+            filter,
+            messages: VecDeque::new(),
+            fetched: 0,
+            done: false,
+        }
+    }
+
+    /// Queue the messages of the page requested with the current filter and move the filter to
+    /// the next page.
+    fn push_page(&mut self, page: GetMessagesResponse) {
+        let count = page.messages.len();
+        self.fetched += count;
+
+        // The total is not reported by every server, in which case it defaults to 0.
+        let short_page = matches!(self.filter.page.page_size, Some(size) if count < size as usize);
+        let reached_total = page.total > 0 && self.fetched as i64 >= page.total;
+        self.done = count == 0 || short_page || reached_total;
+
+        self.messages.extend(page.messages);
+        self.filter.page.page += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_pages_stop_at_total_or_short_page() {
+        use crate::domain::PageFilter;
+
+        let page = |total: i64, ids: &[&str]| -> GetMessagesResponse {
+            let messages = ids
+                .iter()
+                .map(|id| {
+                    serde_json::json!({
+                        "ID": id,
+                        "LabelIDs": [],
+                        "Subject": "",
+                        "SenderAddress": "",
+                        "Unread": 0,
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::from_value(serde_json::json!({"Total": total, "Messages": messages}))
+                .unwrap()
+        };
+        let filter = MessageFilter {
+            page: PageFilter {
+                label_id: None,
+                page: 0,
+                page_size: Some(2),
+            },
+            ..Default::default()
+        };
+
+        let mut pages = MessagePages::new(filter.clone());
+        pages.push_page(page(3, &["1", "2"]));
+        assert!(!pages.done);
+        assert_eq!(pages.filter.page.page, 1);
+        pages.push_page(page(3, &["3"]));
+        assert!(pages.done);
+        assert_eq!(pages.messages.len(), 3);
+
+        // The last full page reaches the total, no empty page needs to be requested.
+        let mut pages = MessagePages::new(filter.clone());
+        pages.push_page(page(4, &["1", "2"]));
+        pages.push_page(page(4, &["3", "4"]));
+        assert!(pages.done);
+
+        // Without a total the listing continues until a short page.
+        let mut pages = MessagePages::new(filter);
+        pages.push_page(page(0, &["1", "2"]));
+        assert!(!pages.done);
+        pages.push_page(page(0, &[]));
+        assert!(pages.done);
+    }
+}
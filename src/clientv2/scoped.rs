@@ -0,0 +1,76 @@
+use crate::clientv2::Session;
+#[cfg(all(feature = "http-reqwest", feature = "async-send"))]
+use crate::http::ClientAsync;
+use crate::http::{ClientSync, Sequence};
+use std::ops::Deref;
+
+type LogoutFn = Box<dyn FnOnce(Session) + Send>;
+
+/// Guard which performs a best-effort logout of the session when dropped, so that the refresh
+/// token does not stay valid on the server.
+///
+/// Logout failures are logged and never panic. Note that clones of the session obtained through
+/// this guard are logged out as well.
+pub struct ScopedSession {
+    session: Option<Session>,
+    logout: Option<LogoutFn>,
+}
+
+impl ScopedSession {
+    /// Logout using the sync `client`, which blocks the thread dropping the guard.
+    pub fn new_sync<C: ClientSync + Send + 'static>(session: Session, client: C) -> Self {
+        Self {
+            session: Some(session),
+            logout: Some(Box::new(move |session| {
+                if let Err(e) = session.logout().do_sync(&client) {
+                    log::error!("Failed to logout session on drop: {e}");
+                }
+            })),
+        }
+    }
+
+    /// Logout using the async `client`. Since drop can't be async, the logout is spawned on
+    /// `handle` and not awaited. Requires the `async-send` feature, so that the logout can run on
+    /// any worker of the runtime.
+    #[cfg(all(feature = "http-reqwest", feature = "async-send"))]
+    pub fn new_async<C: ClientAsync + Send + 'static>(
+        session: Session,
+        client: C,
+        handle: tokio::runtime::Handle,
+    ) -> Self {
+        Self {
+            session: Some(session),
+            logout: Some(Box::new(move |session| {
+                handle.spawn(async move {
+                    if let Err(e) = session.logout().do_async(&client).await {
+                        log::error!("Failed to logout session on drop: {e}");
+                    }
+                });
+            })),
+        }
+    }
+
+    /// Release the session without logging out.
+    pub fn into_inner(mut self) -> Session {
+        self.logout = None;
+        self.session.take().expect("session is only taken on drop")
+    }
+}
+
+impl Deref for ScopedSession {
+    type Target = Session;
+
+    fn deref(&self) -> &Self::Target {
+        self.session
+            .as_ref()
+            .expect("session is only taken on drop")
+    }
+}
+
+impl Drop for ScopedSession {
+    fn drop(&mut self) {
+        if let (Some(session), Some(logout)) = (self.session.take(), self.logout.take()) {
+            logout(session)
+        }
+    }
+}
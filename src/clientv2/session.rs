@@ -1,18 +1,31 @@
-use crate::clientv2::TotpSession;
+use crate::clientv2::{srp_provider, HvChallenge, SrpError, SrpProofs, TotpSession};
 use crate::domain::{
-    Event, EventId, HumanVerification, HumanVerificationLoginData, Label, LabelType, SecretString,
-    TwoFactorAuth, User, UserUid,
+    Attachment, AttachmentId, Conversation, ConversationId, DraftTemplate, Event, EventDelta,
+    EventId, Fido2AuthenticationOptions, HumanVerification, HumanVerificationLoginData, KeySalt,
+    Label, LabelCount, LabelId, LabelType, LatestEvent, Message, MessageFilter, MessageId,
+    PageFilter, RefreshParams, SecretString, SessionInfo, SessionScope, TwoFactorAuth, User,
+    UserId, UserUid,
 };
 use crate::http;
-use crate::http::{OwnedRequest, RequestDesc, Sequence, SequenceFromState, X_PM_UID_HEADER};
+#[cfg(not(feature = "async-traits"))]
+use crate::http::BoxFuture;
+use crate::http::{
+    BoxedRequest, ClientAsync, ClientSync, MaybeSend, OwnedRequest, RawRequest, RequestData,
+    RequestDesc, Sequence, SequenceFromState,
+};
 use crate::requests::{
-    AuthInfoRequest, AuthInfoResponse, AuthRefreshRequest, AuthRequest, AuthResponse,
-    GetEventRequest, GetLabelsRequest, GetLatestEventRequest, LogoutRequest, TFAStatus,
-    TOTPRequest, UserAuth, UserInfoRequest,
+    AuthInfoRequest, AuthInfoResponse, AuthRefreshRequest, AuthRefreshResponse, AuthRequest,
+    AuthResponse, ConsumeForkRequest, CreateDraftRequest, CreateLabelRequest, DeleteLabelRequest,
+    ForkSessionRequest, GetConversationRequest, GetConversationsRequest, GetEventRequest,
+    GetKeySaltsRequest, GetLabelsRequest, GetLatestEventRequest, GetMessageCountsRequest,
+    GetMessageRequest, GetMessagesRequest, GetSessionsRequest, GetUnauthSessionRequest,
+    LogoutRequest, RevokeAllSessionsRequest, RevokeSessionRequest, SendMessageRequest, TFAStatus,
+    TOTPRequest, UnauthSessionResponse, UpdateLabelRequest, UserAuth, UserInfoRequest,
 };
-use go_srp::SRPAuth;
 use secrecy::{ExposeSecret, Secret};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, thiserror::Error)]
 pub enum LoginError {
@@ -26,31 +39,56 @@ pub enum LoginError {
     ServerProof(String),
     #[error("Account 2FA method ({0})is not supported")]
     Unsupported2FA(TwoFactorAuth),
+    /// Returned by [`Session::login_all`], which can't complete a human verification. The other
+    /// logins return [`SessionType::HumanVerificationRequired`] instead.
     #[error("Human Verification Required'")]
     HumanVerificationRequired(HumanVerification),
     #[error("Failed to calculate SRP Proof: {0}")]
     SRPProof(String),
+    /// The signature of the SRP modulus sent by the server could not be verified. Unlike a
+    /// [`LoginError::ServerProof`] mismatch, this points to the modulus being tampered with.
+    #[error("SRP modulus signature verification failed: {0}")]
+    ModulusSignatureInvalid(String),
+    #[error("2FA was not requested for this session")]
+    TwoFactorNotRequested,
 }
 
 /// Data which can be used to save a session and restore it later.
 pub struct SessionRefreshData {
     pub user_uid: Secret<UserUid>,
     pub token: Secret<String>,
+    /// Parameters the session is refreshed with, to be passed to
+    /// [`Session::refresh_with_params`] when restoring it.
+    pub refresh_params: RefreshParams,
 }
 
 impl PartialEq for SessionRefreshData {
     fn eq(&self, other: &Self) -> bool {
         self.user_uid.expose_secret() == other.user_uid.expose_secret()
             && self.token.expose_secret() == other.token.expose_secret()
+            && self.refresh_params == other.refresh_params
     }
 }
 
 impl Eq for SessionRefreshData {}
 
+impl std::fmt::Debug for SessionRefreshData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionRefreshData")
+            .field("user_uid", &"[REDACTED]")
+            .field("token", &"[REDACTED]")
+            .field("refresh_params", &self.refresh_params)
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 pub enum SessionType {
     Authenticated(Session),
     AwaitingTotp(TotpSession),
+    /// The server requires a human verification before accepting the login, which is resumed
+    /// through the [`HvChallenge`].
+    HumanVerificationRequired(HvChallenge),
 }
 
 /// Authenticated Session from which one can access data/functionality restricted to authenticated
@@ -58,15 +96,60 @@ pub enum SessionType {
 #[derive(Debug, Clone)]
 pub struct Session {
     pub(super) user_auth: Arc<parking_lot::RwLock<UserAuth>>,
+    refresh_retry_delay: Duration,
+    refresh_params: RefreshParams,
+    user_cache: Arc<parking_lot::RwLock<Option<(Instant, User)>>>,
+    /// Set while the login is waiting for the TOTP code.
+    pub(super) totp_pending: Arc<AtomicBool>,
 }
 
+/// Default base delay before retrying a request after the session was refreshed.
+const DEFAULT_REFRESH_RETRY_DELAY: Duration = Duration::from_millis(100);
+
 impl Session {
     fn new(user: UserAuth) -> Self {
         Self {
             user_auth: Arc::new(parking_lot::RwLock::new(user)),
+            refresh_retry_delay: DEFAULT_REFRESH_RETRY_DELAY,
+            refresh_params: RefreshParams::default(),
+            user_cache: Arc::new(parking_lot::RwLock::new(None)),
+            totp_pending: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Create a session from previously obtained credentials without contacting the server, e.g.
+    /// when restoring them from a trusted store. The credentials are only checked by the first
+    /// request, which refreshes the session if the access token is no longer valid.
+    pub fn from_tokens(
+        uid: UserUid,
+        access_token: SecretString,
+        refresh_token: SecretString,
+    ) -> Self {
+        Self::new(UserAuth {
+            uid: Secret::new(uid),
+            access_token,
+            refresh_token,
+            scope: SessionScope::default(),
+            expires_at: None,
+            user_id: None,
+        })
+    }
+
+    /// Set the base delay before a request is retried after an expired session was refreshed.
+    /// A random jitter of up to the same amount is added, so that concurrent requests do not all
+    /// retry at once. A longer `Retry-After` delay requested by the server takes precedence.
+    pub fn with_refresh_retry_delay(mut self, delay: Duration) -> Self {
+        self.refresh_retry_delay = delay;
+        self
+    }
+
+    /// Set the parameters sent when the session is refreshed, e.g. the redirect URI of a custom
+    /// client registration.
+    pub fn with_refresh_params(mut self, params: RefreshParams) -> Self {
+        self.refresh_params = params;
+        self
+    }
+
     pub fn login<'a>(
         username: &'a str,
         password: &'a SecretString,
@@ -81,58 +164,374 @@ impl Session {
         SequenceFromState::new(state, login_sequence_1)
     }
 
+    /// Perform the whole login flow in one go. When the account requires TOTP, `totp_provider` is
+    /// called to obtain the code, which is then submitted. For more control over the flow use
+    /// [`Session::login`].
+    pub fn login_all<'a, P: FnOnce() -> String + MaybeSend + 'a>(
+        username: &'a str,
+        password: &'a SecretString,
+        totp_provider: P,
+        human_verification: Option<HumanVerificationLoginData>,
+    ) -> impl Sequence<Output = Session, Error = LoginError> + 'a {
+        LoginAllSequence {
+            login: Self::login(username, password, human_verification),
+            totp_provider,
+        }
+    }
+
+    /// Submit a TOTP code for this session. On success the session's scope is updated with the
+    /// scopes granted by the server, which are also returned so callers performing a step-up
+    /// authentication can verify the elevation.
     pub fn submit_totp<'a>(
         &'a self,
         code: &'a str,
-    ) -> impl Sequence<Output = (), Error = http::Error> + 'a {
+    ) -> impl Sequence<Output = SessionScope, Error = http::Error> + 'a {
         //self.wrap_request(TOTPRequest::new(code).to_request())
-        self.wrap_request2(TOTPRequest::new(code))
+        self.wrap_request2(TOTPRequest::new(code)).map(move |r| {
+            let mut writer = self.user_auth.write();
+            if !r.scopes.is_empty() {
+                writer.scope = SessionScope::from(r.scopes);
+            }
+            Ok(writer.scope.clone())
+        })
     }
 
+    /// Re-authenticate the user of this session with `password` to elevate its scope, as required
+    /// by sensitive operations. On success the session's tokens and scope are replaced by those of
+    /// the new authentication and the session is returned as with [`Session::login`], including
+    /// [`SessionType::HumanVerificationRequired`] when the server asks for a human verification.
+    /// The returned session shares its state with `self`.
+    pub fn unlock<'a>(
+        &'a self,
+        password: &'a SecretString,
+    ) -> impl Sequence<Output = SessionType, Error = LoginError> + 'a {
+        self.get_user().state(move |user| {
+            let srp_password = password.clone();
+            let data = self.with_session_auth(
+                AuthInfoRequest {
+                    username: &user.name,
+                }
+                .build(),
+            );
+            OwnedRequest::<http::JsonResponse<AuthInfoResponse>>::new(data)
+                .map_blocking(move |auth_info_response| -> Result<_, LoginError> {
+                    let (proof, session) =
+                        generate_srp_proof(&user.name, &srp_password, auth_info_response)?;
+                    Ok((user.name, proof, session))
+                })
+                .state(move |(username, proof, session)| {
+                    let data = self.with_session_auth(
+                        AuthRequest {
+                            username: &username,
+                            client_ephemeral: &proof.client_ephemeral,
+                            client_proof: &proof.client_proof,
+                            srp_session: &session,
+                            human_verification: &None,
+                        }
+                        .build(),
+                    );
+                    OwnedRequest::<http::JsonResponse<AuthResponse>>::new(data)
+                        .map(move |auth_response| -> Result<_, LoginError> {
+                            let st = validate_server_proof(&proof, auth_response)?;
+                            Ok(self.adopt_auth(st))
+                        })
+                        .or_else(move |e| human_verification_challenge(e, &username, password))
+                })
+        })
+    }
+
+    /// Authenticate `data` as this session, so that re-authenticating elevates the existing session
+    /// instead of creating another one on the server.
+    fn with_session_auth(&self, data: RequestData) -> RequestData {
+        let borrow = self.user_auth.read();
+        data.pm_uid(borrow.uid.expose_secret())
+            .bearer_token(borrow.access_token.expose_secret())
+    }
+
+    /// Replace the authentication of this session with the one from `st`, so that all the clones
+    /// of this session observe it.
+    fn adopt_auth(&self, st: SessionType) -> SessionType {
+        let new = match &st {
+            SessionType::Authenticated(s) => s,
+            SessionType::AwaitingTotp(t) => &t.0,
+            SessionType::HumanVerificationRequired(_) => return st,
+        };
+        self.invalidate_cached_user();
+        *self.user_auth.write() = new.user_auth.read().clone();
+        self.totp_pending
+            .store(new.totp_pending.load(Ordering::SeqCst), Ordering::SeqCst);
+
+        match st {
+            SessionType::Authenticated(_) => SessionType::Authenticated(self.clone()),
+            SessionType::AwaitingTotp(t) => {
+                SessionType::AwaitingTotp(TotpSession(self.clone(), t.1))
+            }
+            SessionType::HumanVerificationRequired(c) => SessionType::HumanVerificationRequired(c),
+        }
+    }
+
+    /// When the access token expires, if the server reported its lifetime.
+    pub fn access_token_expires_at(&self) -> Option<Instant> {
+        self.user_auth.read().expires_at
+    }
+
+    /// Check whether the access token expires within `within`, so that it can be refreshed with
+    /// [`Session::refresh_now`] before requests start failing. Always false if the expiry is not
+    /// known.
+    pub fn needs_refresh(&self, within: Duration) -> bool {
+        self.access_token_expires_at()
+            .is_some_and(|expires_at| expires_at <= Instant::now() + within)
+    }
+
+    /// Id of the user this session belongs to, which unlike the UID of the session is not
+    /// secret, e.g. to tell apart the sessions of several accounts. Sessions which were not
+    /// created by a login only learn it once the user is fetched with [`Session::get_user`].
+    pub fn user_id(&self) -> Option<UserId> {
+        self.user_auth.read().user_id.clone()
+    }
+
+    /// Scopes currently granted to this session.
+    pub fn scope(&self) -> SessionScope {
+        self.user_auth.read().scope.clone()
+    }
+
+    /// Restore a session from its refresh token with the default [`RefreshParams`], use
+    /// [`Session::refresh_with_params`] for sessions which were refreshed with custom ones.
     pub fn refresh<'a>(
         user_uid: &'a UserUid,
         token: &'a str,
     ) -> impl Sequence<Output = Self, Error = http::Error> + 'a {
-        AuthRefreshRequest::new(user_uid, token)
+        Self::refresh_with_params(user_uid, token, RefreshParams::default())
+    }
+
+    /// Same as [`Session::refresh`], but refreshes the session with `params`, which are kept for
+    /// the later refreshes of the session.
+    pub fn refresh_with_params<'a>(
+        user_uid: &'a UserUid,
+        token: &'a str,
+        params: RefreshParams,
+    ) -> impl Sequence<Output = Self, Error = http::Error> + 'a {
+        let request = AuthRefreshRequest::new(user_uid, token)
+            .with_params(&params)
+            .to_request();
+        request.map(move |r| {
+            let user = UserAuth::from_auth_refresh_response(r);
+            Ok(Session::new(user).with_refresh_params(params))
+        })
+    }
+
+    /// Fork this session for the client `child_client_id`, e.g. a companion app. The returned
+    /// selector is handed over to the child, which obtains its own session with
+    /// [`Session::from_fork`] without the user entering their credentials again.
+    pub fn fork<'a, 'b: 'a>(
+        &'b self,
+        child_client_id: &'a str,
+    ) -> impl Sequence<Output = String, Error = http::Error> + 'a {
+        self.wrap_request2(ForkSessionRequest::new(child_client_id))
+            .map(|r| Ok(r.selector))
+    }
+
+    /// Create a session from the `selector` returned by [`Session::fork`].
+    pub fn from_fork(selector: &str) -> impl Sequence<Output = Self, Error = http::Error> + '_ {
+        Self::from_fork_with_refresh_params(selector, RefreshParams::default())
+    }
+
+    /// Same as [`Session::from_fork`], but refreshes the session with `params`, which are kept for
+    /// the later refreshes of the session.
+    pub fn from_fork_with_refresh_params(
+        selector: &str,
+        params: RefreshParams,
+    ) -> impl Sequence<Output = Self, Error = http::Error> + '_ {
+        ConsumeForkRequest::new(selector)
             .to_request()
-            .map(|r| {
-                let user = UserAuth::from_auth_refresh_response(r);
-                Ok(Session::new(user))
+            .state(move |r| {
+                let request = AuthRefreshRequest::new(&r.uid, r.refresh_token.expose_secret())
+                    .with_params(&params)
+                    .to_request();
+                request.map(move |r| -> Result<Self, http::Error> {
+                    let user = UserAuth::from_auth_refresh_response(r);
+                    Ok(Session::new(user).with_refresh_params(params))
+                })
             })
     }
 
-    pub fn get_user(&self) -> impl Sequence<Output = User> + '_ {
+    /// Refresh the session's tokens now rather than waiting for a request to fail because they
+    /// expired. Fails with [`http::Error::ScopeDowngraded`] if the server granted fewer scopes than
+    /// before, or with [`http::Error::SessionExpired`] if the refresh token was revoked, in which
+    /// cases a new login is required.
+    pub fn refresh_now(&self) -> impl Sequence<Output = (), Error = http::Error> + '_ {
+        let request = {
+            let borrow = self.user_auth.read();
+            AuthRefreshRequest::new(
+                borrow.uid.expose_secret(),
+                borrow.refresh_token.expose_secret(),
+            )
+            .with_params(&self.refresh_params)
+            .to_request()
+        };
+
+        request.map(move |resp| self.apply_auth_refresh(resp))
+    }
+
+    /// Replace the session's authentication with the result of a token refresh. The new tokens
+    /// are always stored, as the previous ones are no longer valid.
+    fn apply_auth_refresh(&self, resp: AuthRefreshResponse) -> Result<(), http::Error> {
+        self.invalidate_cached_user();
+        let mut writer = self.user_auth.write();
+        let from = writer.scope.clone();
+        let user_id = writer.user_id.take();
+        *writer = UserAuth::from_auth_refresh_response(resp);
+        writer.user_id = user_id;
+        if writer.scope.is_downgrade_of(&from) {
+            log::warn!(
+                "Session scope downgraded from '{from}' to '{}'",
+                writer.scope
+            );
+            return Err(http::Error::ScopeDowngraded {
+                from,
+                to: writer.scope.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn get_user(&self) -> impl Sequence<Output = User, Error = http::Error> + '_ {
         //self.wrap_request(UserInfoRequest {}.to_request())
         //    .map(|r| -> Result<User, http::Error> { Ok(r.user) })
         self.wrap_request2(UserInfoRequest {})
-            .map(|r| -> Result<User, http::Error> { Ok(r.user) })
+            .map(move |r| -> Result<User, http::Error> {
+                let mut writer = self.user_auth.write();
+                if writer.user_id.is_none() {
+                    writer.user_id = Some(r.user.id.clone());
+                }
+                Ok(r.user)
+            })
+    }
+
+    /// Get the salts of the user's keys. Together with the user's password and the private keys
+    /// from [`User::keys`], these allow the keys to be unlocked locally.
+    pub fn get_key_salts(&self) -> impl Sequence<Output = Vec<KeySalt>, Error = http::Error> + '_ {
+        self.wrap_request2(GetKeySaltsRequest {})
+            .map(|r| Ok(r.key_salts))
+    }
+
+    /// Get the user, reusing the result of a previous call if it was fetched less than `ttl` ago.
+    /// The cache is shared by all clones of the session and is cleared on logout and whenever
+    /// the session is refreshed, see also [`Session::invalidate_cached_user`].
+    pub fn cached_user(
+        &self,
+        ttl: Duration,
+    ) -> impl Sequence<Output = User, Error = http::Error> + '_ {
+        CachedUserSequence { session: self, ttl }
+    }
+
+    /// Clear the user cached by [`Session::cached_user`].
+    pub fn invalidate_cached_user(&self) {
+        *self.user_cache.write() = None;
     }
 
     pub fn logout(&self) -> impl Sequence<Output = (), Error = http::Error> + '_ {
-        //self.wrap_request(LogoutRequest {}.to_request())
-        self.wrap_request2(LogoutRequest {})
+        self.wrap_request2(LogoutRequest {}).map(move |_| {
+            self.invalidate_cached_user();
+            Ok(())
+        })
+    }
+
+    /// List all the active sessions of the user.
+    pub fn get_sessions(
+        &self,
+    ) -> impl Sequence<Output = Vec<SessionInfo>, Error = http::Error> + '_ {
+        self.wrap_request2(GetSessionsRequest {})
+            .map(|r| Ok(r.sessions))
+    }
+
+    /// Revoke a single session of the user identified by `uid`.
+    pub fn revoke_session<'a, 'b: 'a>(
+        &'b self,
+        uid: &'a UserUid,
+    ) -> impl Sequence<Output = (), Error = http::Error> + 'a {
+        self.wrap_request2(RevokeSessionRequest::new(uid))
+    }
+
+    /// Revoke all the sessions of the user, including this one.
+    pub fn revoke_all_sessions(&self) -> impl Sequence<Output = (), Error = http::Error> + '_ {
+        self.wrap_request2(RevokeAllSessionsRequest {})
     }
 
+    /// Get the id of the latest event, from which [`Session::get_event`] can start polling.
     pub fn get_latest_event(&self) -> impl Sequence<Output = EventId, Error = http::Error> + '_ {
-        //self.wrap_request(GetLatestEventRequest {}.to_request())
-        //    .map(|r| Ok(r.event_id))
+        self.get_latest_event_info().map(|r| Ok(r.event_id))
+    }
+
+    /// Same as [`Session::get_latest_event`], but also returns whether more events are pending
+    /// and which data needs a full resync.
+    pub fn get_latest_event_info(
+        &self,
+    ) -> impl Sequence<Output = LatestEvent, Error = http::Error> + '_ {
         self.wrap_request2(GetLatestEventRequest {})
-            .map(|r| Ok(r.event_id))
     }
 
+    /// Get the changes since the event `id`.
     pub fn get_event<'a, 'b: 'a>(
         &'b self,
         id: &'a EventId,
     ) -> impl Sequence<Output = Event, Error = http::Error> + 'a {
-        //self.wrap_request(GetEventRequest::new(id).to_request())
         self.wrap_request2(GetEventRequest::new(id))
     }
 
+    /// Follow the events since `from` until the latest one, folding their message changes into a
+    /// single [`EventDelta`]. Stops early if an event requires a full resync or after fetching
+    /// `max_events` events, in which case [`EventDelta::more`] is set and polling can resume from
+    /// [`EventDelta::new_cursor`]. At least one event is always fetched.
+    pub fn poll_delta<'a, 'b: 'a>(
+        &'b self,
+        from: &'a EventId,
+        max_events: usize,
+    ) -> impl Sequence<Output = EventDelta, Error = http::Error> + 'a {
+        PollDeltaSequence {
+            session: self,
+            from,
+            max_events,
+        }
+    }
+
+    /// Get the changes since the event `after`, asking the server to hold the connection until
+    /// a new event is available or `wait` has elapsed. Returns `None` if nothing changed in the
+    /// meantime, including when the request timed out.
+    ///
+    /// Servers which do not support long polling answer immediately, in which case this behaves
+    /// like [`Session::get_event`].
+    pub fn get_event_longpoll<'a, 'b: 'a>(
+        &'b self,
+        after: &'a EventId,
+        wait: Duration,
+    ) -> impl Sequence<Output = Option<Event>, Error = http::Error> + 'a {
+        self.wrap_request2(GetEventRequest::new(after).with_wait(wait))
+            .map(move |event| Ok(event.has_changes_since(after).then_some(event)))
+            .or_else(|e| match e {
+                http::Error::Timeout(_) => Ok(None),
+                e => Err(e),
+            })
+    }
+
+    /// UID and access token of the session, for connections which are not made through the http
+    /// clients.
+    #[cfg(feature = "events-ws")]
+    pub(crate) fn credentials(&self) -> (String, String) {
+        let borrow = self.user_auth.read();
+        (
+            borrow.uid.expose_secret().to_string(),
+            borrow.access_token.expose_secret().clone(),
+        )
+    }
+
     pub fn get_refresh_data(&self) -> SessionRefreshData {
         let reader = self.user_auth.read();
         SessionRefreshData {
             user_uid: reader.uid.clone(),
             token: reader.refresh_token.clone(),
+            refresh_params: self.refresh_params.clone(),
         }
     }
 
@@ -146,8 +545,129 @@ impl Session {
             .map(|r| Ok(r.labels))
     }
 
+    /// Create a new label or folder, optionally nested under `parent_id`.
+    pub fn create_label<'a, 'b: 'a>(
+        &'b self,
+        name: &'a str,
+        color: &'a str,
+        label_type: LabelType,
+        parent_id: Option<&'a LabelId>,
+    ) -> impl Sequence<Output = Label, Error = http::Error> + 'a {
+        self.wrap_request2(CreateLabelRequest::new(name, color, label_type, parent_id))
+            .map(|r| Ok(r.label))
+    }
+
+    /// Update the name, color and parent of the label `id`.
+    pub fn update_label<'a, 'b: 'a>(
+        &'b self,
+        id: &'a LabelId,
+        name: &'a str,
+        color: &'a str,
+        parent_id: Option<&'a LabelId>,
+    ) -> impl Sequence<Output = Label, Error = http::Error> + 'a {
+        self.wrap_request2(UpdateLabelRequest::new(id, name, color, parent_id))
+            .map(|r| Ok(r.label))
+    }
+
+    /// Delete the label or folder `id`.
+    pub fn delete_label<'a, 'b: 'a>(
+        &'b self,
+        id: &'a LabelId,
+    ) -> impl Sequence<Output = (), Error = http::Error> + 'a {
+        self.wrap_request2(DeleteLabelRequest::new(id))
+    }
+
+    /// Get the total and unread message counts of every label.
+    pub fn get_counts(&self) -> impl Sequence<Output = Vec<LabelCount>, Error = http::Error> + '_ {
+        self.wrap_request2(GetMessageCountsRequest)
+            .map(|r| Ok(r.counts))
+    }
+
+    /// Get the message `id`, including the metadata of its attachments.
+    pub fn get_message<'a, 'b: 'a>(
+        &'b self,
+        id: &'a MessageId,
+    ) -> impl Sequence<Output = Message, Error = http::Error> + 'a {
+        self.wrap_request2(GetMessageRequest::new(id))
+            .map(|r| Ok(r.message))
+    }
+
+    /// Get the name, size, mime type and key packets of the attachment `att` of message `msg`
+    /// without downloading its data. Returns `None` if the message has no such attachment.
+    pub fn get_attachment_info<'a, 'b: 'a>(
+        &'b self,
+        msg: &'a MessageId,
+        att: &'a AttachmentId,
+    ) -> impl Sequence<Output = Option<Attachment>, Error = http::Error> + 'a {
+        self.get_message(msg)
+            .map(move |m| Ok(m.attachments.into_iter().find(|a| a.id == *att)))
+    }
+
+    /// Create a new draft message from `template`.
+    pub fn create_draft<'a, 'b: 'a>(
+        &'b self,
+        template: &'a DraftTemplate,
+    ) -> impl Sequence<Output = Message, Error = http::Error> + 'a {
+        self.wrap_request2(CreateDraftRequest::new(template))
+            .map(|r| Ok(r.message))
+    }
+
+    /// Send the draft message `id` using the encrypted message `packages` prepared by the caller.
+    pub fn send_message<'a, 'b: 'a>(
+        &'b self,
+        id: &'a MessageId,
+        packages: &'a serde_json::Value,
+    ) -> impl Sequence<Output = Message, Error = http::Error> + 'a {
+        self.wrap_request2(SendMessageRequest::new(id, packages))
+            .map(|r| Ok(r.sent))
+    }
+
+    /// List the messages matching `filter`.
+    pub fn get_messages<'a, 'b: 'a>(
+        &'b self,
+        filter: &'a MessageFilter,
+    ) -> impl Sequence<Output = Vec<Message>, Error = http::Error> + 'a {
+        self.wrap_request2(GetMessagesRequest::new(filter))
+            .map(|r| Ok(r.messages))
+    }
+
+    /// List the conversations matching `filter`.
+    pub fn get_conversations<'a, 'b: 'a>(
+        &'b self,
+        filter: &'a PageFilter,
+    ) -> impl Sequence<Output = Vec<Conversation>, Error = http::Error> + 'a {
+        self.wrap_request2(GetConversationsRequest::new(filter))
+            .map(|r| Ok(r.conversations))
+    }
+
+    /// Get the conversation `id` and its messages.
+    pub fn get_conversation<'a, 'b: 'a>(
+        &'b self,
+        id: &'a ConversationId,
+    ) -> impl Sequence<Output = (Conversation, Vec<Message>), Error = http::Error> + 'a {
+        self.wrap_request2(GetConversationRequest::new(id))
+            .map(|r| Ok((r.conversation, r.messages)))
+    }
+
+    /// Execute a type erased request with this session's authentication.
+    pub fn request_boxed<'a, O: MaybeSend + 'a>(
+        &'a self,
+        r: BoxedRequest<O>,
+    ) -> impl Sequence<Output = O, Error = http::Error> + 'a {
+        self.wrap_request2(RawRequest(r.data)).map(r.decode)
+    }
+
+    /// Execute a custom request with this session's authentication. Expired sessions are refreshed
+    /// automatically, as with all other session requests.
+    pub fn request<'a, 'b: 'a, R: RequestDesc + MaybeSend + 'a>(
+        &'b self,
+        r: R,
+    ) -> impl Sequence<Output = R::Output, Error = http::Error> + 'a {
+        self.wrap_request2(r)
+    }
+
     #[inline(always)]
-    fn wrap_request2<'a, 'b: 'a, R: RequestDesc + 'a>(
+    fn wrap_request2<'a, 'b: 'a, R: RequestDesc + MaybeSend + 'a>(
         &'b self,
         r: R,
     ) -> impl Sequence<Output = R::Output, Error = http::Error> + 'a {
@@ -155,8 +675,283 @@ impl Session {
     }
 }
 
+/// Shorthands which execute the most common sequences directly, easing the migration from APIs
+/// with separate sync and async methods. Everything else is only available as a [`Sequence`].
+impl Session {
+    pub fn get_user_sync<T: ClientSync>(&self, client: &T) -> Result<User, http::Error> {
+        self.get_user().do_sync(client)
+    }
+
+    pub async fn get_user_async<T: ClientAsync>(&self, client: &T) -> Result<User, http::Error> {
+        self.get_user().do_async(client).await
+    }
+
+    pub fn logout_sync<T: ClientSync>(&self, client: &T) -> Result<(), http::Error> {
+        self.logout().do_sync(client)
+    }
+
+    pub async fn logout_async<T: ClientAsync>(&self, client: &T) -> Result<(), http::Error> {
+        self.logout().do_async(client).await
+    }
+
+    pub fn get_latest_event_sync<T: ClientSync>(&self, client: &T) -> Result<EventId, http::Error> {
+        self.get_latest_event().do_sync(client)
+    }
+
+    pub async fn get_latest_event_async<T: ClientAsync>(
+        &self,
+        client: &T,
+    ) -> Result<EventId, http::Error> {
+        self.get_latest_event().do_async(client).await
+    }
+
+    pub fn get_event_sync<T: ClientSync>(
+        &self,
+        client: &T,
+        id: &EventId,
+    ) -> Result<Event, http::Error> {
+        self.get_event(id).do_sync(client)
+    }
+
+    pub async fn get_event_async<T: ClientAsync>(
+        &self,
+        client: &T,
+        id: &EventId,
+    ) -> Result<Event, http::Error> {
+        self.get_event(id).do_async(client).await
+    }
+}
+
+struct CachedUserSequence<'a> {
+    session: &'a Session,
+    ttl: Duration,
+}
+
+impl<'a> CachedUserSequence<'a> {
+    fn get_cached(&self) -> Option<User> {
+        self.session
+            .user_cache
+            .read()
+            .as_ref()
+            .filter(|(fetched, _)| fetched.elapsed() < self.ttl)
+            .map(|(_, user)| user.clone())
+    }
+
+    fn set_cached(&self, user: &User) {
+        *self.session.user_cache.write() = Some((Instant::now(), user.clone()));
+    }
+}
+
+impl<'s> Sequence for CachedUserSequence<'s> {
+    type Output = User;
+    type Error = http::Error;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        if let Some(user) = self.get_cached() {
+            return Ok(user);
+        }
+
+        let user = self.session.get_user().do_sync(client)?;
+        self.set_cached(&user);
+        Ok(user)
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> BoxFuture<'a, Result<Self::Output, Self::Error>>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(async move {
+            if let Some(user) = self.get_cached() {
+                return Ok(user);
+            }
+
+            let user = self.session.get_user().do_async(client).await?;
+            self.set_cached(&user);
+            Ok(user)
+        })
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl std::future::Future<Output = Result<Self::Output, Self::Error>> + 'a
+    where
+        Self: 'a,
+    {
+        async move {
+            if let Some(user) = self.get_cached() {
+                return Ok(user);
+            }
+
+            let user = self.session.get_user().do_async(client).await?;
+            self.set_cached(&user);
+            Ok(user)
+        }
+    }
+}
+
+struct PollDeltaSequence<'a> {
+    session: &'a Session,
+    from: &'a EventId,
+    max_events: usize,
+}
+
+impl<'a> PollDeltaSequence<'a> {
+    /// Apply the `fetched`-th `event` to `delta` and check whether more events should be fetched.
+    fn fold(&self, delta: &mut EventDelta, event: Event, fetched: usize) -> bool {
+        let progressed = event.event_id != delta.new_cursor;
+        delta.apply(&event);
+        // Avoid reporting more events if the server keeps returning the same one.
+        delta.more &= progressed;
+        delta.more && !delta.needs_resync && fetched < self.max_events
+    }
+}
+
+impl<'s> Sequence for PollDeltaSequence<'s> {
+    type Output = EventDelta;
+    type Error = http::Error;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        let mut delta = EventDelta::new(self.from.clone());
+        let mut fetched = 0;
+        loop {
+            let event = self.session.get_event(&delta.new_cursor).do_sync(client)?;
+            fetched += 1;
+            if !self.fold(&mut delta, event, fetched) {
+                return Ok(delta);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> BoxFuture<'a, Result<Self::Output, Self::Error>>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(async move {
+            let mut delta = EventDelta::new(self.from.clone());
+            let mut fetched = 0;
+            loop {
+                let event = self
+                    .session
+                    .get_event(&delta.new_cursor)
+                    .do_async(client)
+                    .await?;
+                fetched += 1;
+                if !self.fold(&mut delta, event, fetched) {
+                    return Ok(delta);
+                }
+            }
+        })
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl std::future::Future<Output = Result<Self::Output, Self::Error>> + 'a
+    where
+        Self: 'a,
+    {
+        async move {
+            let mut delta = EventDelta::new(self.from.clone());
+            let mut fetched = 0;
+            loop {
+                let event = self
+                    .session
+                    .get_event(&delta.new_cursor)
+                    .do_async(client)
+                    .await?;
+                fetched += 1;
+                if !self.fold(&mut delta, event, fetched) {
+                    return Ok(delta);
+                }
+            }
+        }
+    }
+}
+
+struct LoginAllSequence<L, P> {
+    login: L,
+    totp_provider: P,
+}
+
+impl<L, P> Sequence for LoginAllSequence<L, P>
+where
+    L: Sequence<Output = SessionType, Error = LoginError> + MaybeSend,
+    P: FnOnce() -> String + MaybeSend,
+{
+    type Output = Session;
+    type Error = LoginError;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        match self.login.do_sync(client)? {
+            SessionType::Authenticated(s) => Ok(s),
+            SessionType::AwaitingTotp(t) => {
+                let code = (self.totp_provider)();
+                Ok(t.submit_totp(&code).do_sync(client)?)
+            }
+            SessionType::HumanVerificationRequired(c) => {
+                Err(LoginError::HumanVerificationRequired(c.verification))
+            }
+        }
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> BoxFuture<'a, Result<Self::Output, Self::Error>>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(async move {
+            match self.login.do_async(client).await? {
+                SessionType::Authenticated(s) => Ok(s),
+                SessionType::AwaitingTotp(t) => {
+                    let code = (self.totp_provider)();
+                    Ok(t.submit_totp(&code).do_async(client).await?)
+                }
+                SessionType::HumanVerificationRequired(c) => {
+                    Err(LoginError::HumanVerificationRequired(c.verification))
+                }
+            }
+        })
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl std::future::Future<Output = Result<Self::Output, Self::Error>> + 'a
+    where
+        Self: 'a,
+    {
+        async move {
+            match self.login.do_async(client).await? {
+                SessionType::Authenticated(s) => Ok(s),
+                SessionType::AwaitingTotp(t) => {
+                    let code = (self.totp_provider)();
+                    Ok(t.submit_totp(&code).do_async(client).await?)
+                }
+                SessionType::HumanVerificationRequired(c) => {
+                    Err(LoginError::HumanVerificationRequired(c.verification))
+                }
+            }
+        }
+    }
+}
+
 fn validate_server_proof(
-    proof: &SRPAuth,
+    proof: &SrpProofs,
     auth_response: AuthResponse,
 ) -> Result<SessionType, LoginError> {
     if proof.expected_server_proof != auth_response.server_proof {
@@ -166,26 +961,49 @@ fn validate_server_proof(
     }
 
     let tfa_enabled = auth_response.tfa.enabled;
+    let fido2_options = match tfa_enabled {
+        TFAStatus::TotpOrFIDO2 => Fido2AuthenticationOptions::try_from(
+            &auth_response.tfa.fido2_info.authentication_options,
+        )
+        .map_err(|e| log::warn!("Failed to parse FIDO2 authentication options: {e}"))
+        .ok(),
+        _ => None,
+    };
     let user = UserAuth::from_auth_response(auth_response);
 
     let session = Session::new(user);
 
     match tfa_enabled {
         TFAStatus::None => Ok(SessionType::Authenticated(session)),
-        TFAStatus::Totp => Ok(SessionType::AwaitingTotp(TotpSession(session))),
+        TFAStatus::Totp | TFAStatus::TotpOrFIDO2 => {
+            session.totp_pending.store(true, Ordering::SeqCst);
+            Ok(SessionType::AwaitingTotp(TotpSession(
+                session,
+                fido2_options,
+            )))
+        }
         TFAStatus::FIDO2 => Err(LoginError::Unsupported2FA(TwoFactorAuth::FIDO2)),
-        TFAStatus::TotpOrFIDO2 => Ok(SessionType::AwaitingTotp(TotpSession(session))),
     }
 }
 
-fn map_human_verification_err(e: LoginError) -> LoginError {
-    if let LoginError::Request(http::Error::API(e)) = &e {
-        if let Ok(hv) = e.try_get_human_verification_details() {
-            return LoginError::HumanVerificationRequired(hv);
+/// Turn a human verification request of the server into
+/// [`SessionType::HumanVerificationRequired`], so that the login can be resumed.
+fn human_verification_challenge(
+    e: LoginError,
+    username: &str,
+    password: &SecretString,
+) -> Result<SessionType, LoginError> {
+    if let LoginError::Request(http::Error::API(api_err)) = &e {
+        if let Ok(verification) = api_err.try_get_human_verification_details() {
+            return Ok(SessionType::HumanVerificationRequired(HvChallenge {
+                verification,
+                username: username.to_string(),
+                password: password.clone(),
+            }));
         }
     }
 
-    e
+    Err(e)
 }
 
 struct State<'a> {
@@ -196,66 +1014,119 @@ struct State<'a> {
 
 struct LoginState<'a> {
     username: &'a str,
-    proof: SRPAuth,
+    password: &'a SecretString,
+    proof: SrpProofs,
     session: String,
+    unauth: Option<UnauthSessionResponse>,
     hv: Option<HumanVerificationLoginData>,
 }
 
-fn generate_login_state(
-    state: State,
+/// Authenticate `data` with the unauthenticated session of the login flow, if there is one.
+fn with_unauth_session(data: RequestData, unauth: &Option<UnauthSessionResponse>) -> RequestData {
+    match unauth {
+        Some(unauth) => data
+            .pm_uid(&unauth.uid)
+            .bearer_token(unauth.access_token.expose_secret()),
+        None => data,
+    }
+}
+
+fn generate_srp_proof(
+    username: &str,
+    password: &SecretString,
     auth_info_response: AuthInfoResponse,
-) -> Result<LoginState, LoginError> {
-    let proof = SRPAuth::generate(
-        state.username,
-        state.password.expose_secret(),
-        auth_info_response.version,
-        &auth_info_response.salt,
-        &auth_info_response.modulus,
-        &auth_info_response.server_ephemeral,
-    )
-    .map_err(LoginError::ServerProof)?;
-
-    Ok(LoginState {
-        username: state.username,
-        proof,
-        session: auth_info_response.srp_session,
-        hv: state.hv,
-    })
+) -> Result<(SrpProofs, String), LoginError> {
+    let provider = srp_provider().ok_or_else(|| {
+        LoginError::SRPProof("No SRP provider available, see set_srp_provider".to_string())
+    })?;
+    let proof = provider
+        .generate(
+            username,
+            password.expose_secret(),
+            auth_info_response.version,
+            &auth_info_response.salt,
+            &auth_info_response.modulus,
+            &auth_info_response.server_ephemeral,
+        )
+        .map_err(|e| match e {
+            SrpError::ModulusSignature(e) => LoginError::ModulusSignatureInvalid(e),
+            SrpError::Proof(e) => LoginError::SRPProof(e),
+        })?;
+
+    Ok((proof, auth_info_response.srp_session))
 }
 
 fn login_sequence_2(
     login_state: LoginState,
 ) -> impl Sequence<Output = SessionType, Error = LoginError> + '_ {
-    AuthRequest {
+    let data = AuthRequest {
         username: login_state.username,
         client_ephemeral: &login_state.proof.client_ephemeral,
         client_proof: &login_state.proof.client_proof,
         srp_session: &login_state.session,
         human_verification: &login_state.hv,
     }
-    .to_request()
-    .map(move |auth_response| {
-        validate_server_proof(&login_state.proof, auth_response).map_err(map_human_verification_err)
-    })
+    .build();
+
+    let (username, password) = (login_state.username, login_state.password);
+    OwnedRequest::<http::JsonResponse<AuthResponse>>::new(with_unauth_session(
+        data,
+        &login_state.unauth,
+    ))
+    .map(move |auth_response| validate_server_proof(&login_state.proof, auth_response))
+    .or_else(move |e| human_verification_challenge(e, username, password))
 }
 
 fn login_sequence_1(st: State) -> impl Sequence<Output = SessionType, Error = LoginError> + '_ {
-    AuthInfoRequest {
+    // Servers which predate unauthenticated sessions don't have the endpoint, in which case the
+    // login proceeds without one.
+    GetUnauthSessionRequest
+        .to_request()
+        .map(|unauth| -> Result<_, http::Error> { Ok(Some(unauth)) })
+        .or_else(|e| match &e {
+            http::Error::API(api_err) if api_err.http_code == 404 => Ok(None),
+            _ => Err(e),
+        })
+        .state(move |unauth| login_sequence_auth_info(st, unauth))
+}
+
+fn login_sequence_auth_info<'a>(
+    st: State<'a>,
+    unauth: Option<UnauthSessionResponse>,
+) -> impl Sequence<Output = SessionType, Error = LoginError> + 'a {
+    let username = st.username.to_string();
+    let password = st.password.clone();
+    let data = AuthInfoRequest {
         username: st.username,
     }
-    .to_request()
-    .map(move |auth_info_response| generate_login_state(st, auth_info_response))
-    .state(login_sequence_2)
+    .build();
+
+    OwnedRequest::<http::JsonResponse<AuthInfoResponse>>::new(with_unauth_session(data, &unauth))
+        // SRP proof generation is CPU bound, make sure it does not stall async executors.
+        .map_blocking(move |auth_info_response| {
+            generate_srp_proof(&username, &password, auth_info_response)
+        })
+        .map(move |(proof, session)| -> Result<LoginState, LoginError> {
+            Ok(LoginState {
+                username: st.username,
+                password: st.password,
+                proof,
+                session,
+                unauth,
+                hv: st.hv,
+            })
+        })
+        .state(login_sequence_2)
 }
 
-fn wrap_session_request<'a, R: RequestDesc + 'a>(
+fn wrap_session_request<'a, R: RequestDesc + MaybeSend + 'a>(
     session: &'a Session,
     r: R,
 ) -> impl Sequence<Output = R::Output, Error = http::Error> + 'a {
     let data = {
         let borrow = session.user_auth.read();
         r.build()
-            .header(X_PM_UID_HEADER, borrow.uid.expose_secret().as_str())
+            .pm_uid(borrow.uid.expose_secret())
             .bearer_token(borrow.access_token.expose_secret())
     };
 
@@ -264,22 +1135,24 @@ fn wrap_session_request<'a, R: RequestDesc + 'a>(
         if let http::Error::API(api_err) = &e {
             if api_err.http_code == 401 {
                 log::debug!("Account session expired, attempting refresh");
+                let delay = refresh_retry_delay(session.refresh_retry_delay, api_err.retry_after);
                 return Ok({
                     let borrow = session.user_auth.read();
                     AuthRefreshRequest::new(
                         borrow.uid.expose_secret(),
                         borrow.refresh_token.expose_secret(),
                     )
+                    .with_params(&session.refresh_params)
                     .to_request()
                 }
                 .chain(move |resp| {
+                    session.apply_auth_refresh(resp)?;
                     let data = {
-                        let mut writer = session.user_auth.write();
-                        *writer = UserAuth::from_auth_refresh_response(resp);
-                        data.header(X_PM_UID_HEADER, writer.uid.expose_secret().as_str())
-                            .bearer_token(writer.access_token.expose_secret())
+                        let borrow = session.user_auth.read();
+                        data.pm_uid(borrow.uid.expose_secret())
+                            .bearer_token(borrow.access_token.expose_secret())
                     };
-                    Ok(OwnedRequest::<R::Response>::new(data))
+                    Ok(OwnedRequest::<R::Response>::new(data).delay(delay))
                 }));
             }
         }
@@ -287,3 +1160,187 @@ fn wrap_session_request<'a, R: RequestDesc + 'a>(
         Err(e)
     })
 }
+
+/// Delay of `base` plus up to `base` of random jitter, or `retry_after` if it is longer.
+fn refresh_retry_delay(base: Duration, retry_after: Option<Duration>) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+    // Randomly seeded by the standard library, which avoids pulling in a random number crate.
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    let jitter = base.mul_f64((random % 1000) as f64 / 1000.0);
+    (base + jitter).max(retry_after.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_retry_delay_bounds() {
+        let base = Duration::from_millis(100);
+        let delay = refresh_retry_delay(base, None);
+        assert!(delay >= base && delay <= base * 2);
+
+        let retry_after = Duration::from_secs(5);
+        assert_eq!(refresh_retry_delay(base, Some(retry_after)), retry_after);
+        assert_eq!(refresh_retry_delay(Duration::ZERO, None), Duration::ZERO);
+    }
+
+    #[cfg(feature = "http-ureq")]
+    #[test]
+    fn totp_session_rejects_completed_login() {
+        let session = Session::new(UserAuth {
+            uid: Secret::new(UserUid::from("uid")),
+            access_token: SecretString::new("access".to_string()),
+            refresh_token: SecretString::new("refresh".to_string()),
+            scope: SessionScope::default(),
+            expires_at: None,
+            user_id: None,
+        });
+        let client = http::ClientBuilder::new()
+            .base_url("http://127.0.0.1:1")
+            .build::<http::ureq_client::UReqClient>()
+            .unwrap();
+
+        // The login did not ask for a TOTP code, so no request should be made.
+        let result = TotpSession(session, None)
+            .submit_totp("000000")
+            .do_sync(&client);
+        assert!(matches!(result, Err(LoginError::TwoFactorNotRequested)));
+    }
+
+    #[test]
+    fn login_totp_or_fido2_awaits_totp() {
+        let proof = SrpProofs {
+            client_proof: "client_proof".to_string(),
+            client_ephemeral: "client_ephemeral".to_string(),
+            expected_server_proof: "server_proof".to_string(),
+        };
+        let auth_response = serde_json::from_str::<AuthResponse>(
+            r#"{
+                "UserID": "user_id",
+                "UID": "uid",
+                "AccessToken": "access",
+                "RefreshToken": "refresh",
+                "ServerProof": "server_proof",
+                "Scope": "full",
+                "2FA": {
+                    "Enabled": 3,
+                    "FIDO2": {
+                        "AuthenticationOptions": {"publicKey": {"challenge": [1, 2, 3]}},
+                        "RegisteredKeys": []
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let Ok(SessionType::AwaitingTotp(t)) = validate_server_proof(&proof, auth_response) else {
+            panic!("expected the login to wait for the TOTP code");
+        };
+        assert!(t.0.totp_pending.load(Ordering::SeqCst));
+        assert_eq!(t.fido2_options().unwrap().challenge, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn session_needs_refresh() {
+        let user_auth = |expires_at| UserAuth {
+            uid: Secret::new(UserUid::from("uid")),
+            access_token: SecretString::new("access".to_string()),
+            refresh_token: SecretString::new("refresh".to_string()),
+            scope: SessionScope::default(),
+            expires_at,
+            user_id: None,
+        };
+
+        let session = Session::new(user_auth(Some(Instant::now() + Duration::from_secs(60))));
+        assert!(session.needs_refresh(Duration::from_secs(120)));
+        assert!(!session.needs_refresh(Duration::from_secs(30)));
+
+        let session = Session::new(user_auth(None));
+        assert!(session.access_token_expires_at().is_none());
+        assert!(!session.needs_refresh(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn session_refresh_keeps_user_id() {
+        let session = Session::new(UserAuth {
+            uid: Secret::new(UserUid::from("uid")),
+            access_token: SecretString::new("access".to_string()),
+            refresh_token: SecretString::new("refresh".to_string()),
+            scope: SessionScope::default(),
+            expires_at: None,
+            user_id: Some(UserId("user_id".to_string())),
+        });
+
+        session
+            .apply_auth_refresh(AuthRefreshResponse {
+                uid: "uid".to_string(),
+                token_type: None,
+                access_token: "new_access".to_string(),
+                refresh_token: "new_refresh".to_string(),
+                scope: String::new(),
+                expires_in: None,
+            })
+            .unwrap();
+        assert_eq!(session.user_id(), Some(UserId("user_id".to_string())));
+    }
+
+    #[test]
+    fn login_human_verification_challenge() {
+        use crate::domain::HumanVerificationType;
+        use crate::requests::APIError;
+
+        let body = br#"{"Code": 9001, "Error": "Human verification required", "Details": {"HumanVerificationMethods": ["captcha", "email"], "HumanVerificationToken": "hv_token"}}"#;
+        let err = LoginError::Request(http::Error::API(APIError::with_status_and_body(422, body)));
+        let password = SecretString::new("password".to_string());
+
+        let Ok(SessionType::HumanVerificationRequired(c)) =
+            human_verification_challenge(err, "user", &password)
+        else {
+            panic!("expected a human verification challenge");
+        };
+        assert_eq!(c.token(), "hv_token");
+        assert_eq!(
+            c.methods(),
+            &[HumanVerificationType::Captcha, HumanVerificationType::Email]
+        );
+        assert_eq!(c.username, "user");
+
+        let err = LoginError::ServerProof("invalid".to_string());
+        assert!(matches!(
+            human_verification_challenge(err, "user", &password),
+            Err(LoginError::ServerProof(_))
+        ));
+    }
+
+    #[test]
+    fn session_debug_redacts_credentials() {
+        let user_auth = UserAuth {
+            uid: Secret::new(UserUid::from("uid_value")),
+            access_token: SecretString::new("access_value".to_string()),
+            refresh_token: SecretString::new("refresh_value".to_string()),
+            scope: SessionScope::default(),
+            expires_at: None,
+            user_id: Some(UserId("user_id".to_string())),
+        };
+        let refresh_data = SessionRefreshData {
+            user_uid: Secret::new(UserUid::from("uid_value")),
+            token: Secret::new("refresh_value".to_string()),
+            refresh_params: RefreshParams::default(),
+        };
+
+        for debug in [
+            format!("{user_auth:?}"),
+            format!("{refresh_data:?}"),
+            format!("{:?}", UserUid::from("uid_value")),
+            format!("{:?}", Session::new(user_auth.clone())),
+        ] {
+            assert!(!debug.contains("uid_value"), "{debug}");
+            assert!(!debug.contains("access_value"), "{debug}");
+            assert!(!debug.contains("refresh_value"), "{debug}");
+        }
+        assert!(format!("{user_auth:?}").contains("user_id"));
+    }
+}
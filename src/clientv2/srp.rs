@@ -0,0 +1,144 @@
+#[cfg(feature = "srp-custom")]
+use std::sync::OnceLock;
+
+#[cfg(not(any(feature = "srp-go", feature = "srp-custom")))]
+compile_error!(
+    "Logins need an SRP provider, enable either the `srp-go` or the `srp-custom` feature"
+);
+
+/// Client side proofs of the SRP login, base64 encoded.
+#[derive(Debug, Clone)]
+pub struct SrpProofs {
+    pub client_proof: String,
+    pub client_ephemeral: String,
+    pub expected_server_proof: String,
+}
+
+/// Reasons why [`SrpProvider::generate`] can fail.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SrpError {
+    /// The signature of the modulus could not be verified against the server key.
+    ModulusSignature(String),
+    /// Any other failure while computing the proofs.
+    Proof(String),
+}
+
+/// Computes the client side proofs of the SRP login.
+///
+/// The modulus is sent base64 encoded inside a PGP signed message, implementations must verify
+/// its signature against Proton's server key before using it. Salt and server ephemeral are std
+/// base64 encoded and `version` selects how the password is hashed.
+pub trait SrpProvider: Send + Sync {
+    fn generate(
+        &self,
+        username: &str,
+        password: &str,
+        version: i64,
+        salt: &str,
+        modulus: &str,
+        server_ephemeral: &str,
+    ) -> Result<SrpProofs, SrpError>;
+}
+
+/// [`SrpProvider`] backed by the bindings to Proton's go-srp library.
+#[cfg(feature = "srp-go")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct GoSrpProvider;
+
+#[cfg(feature = "srp-go")]
+impl SrpProvider for GoSrpProvider {
+    fn generate(
+        &self,
+        username: &str,
+        password: &str,
+        version: i64,
+        salt: &str,
+        modulus: &str,
+        server_ephemeral: &str,
+    ) -> Result<SrpProofs, SrpError> {
+        let auth =
+            go_srp::SRPAuth::generate(username, password, version, salt, modulus, server_ephemeral)
+                .map_err(|e| match e {
+                    go_srp::SRPError::ModulusSignature(e) => SrpError::ModulusSignature(e),
+                    go_srp::SRPError::Proof(e) => SrpError::Proof(e),
+                })?;
+
+        Ok(SrpProofs {
+            client_proof: auth.client_proof,
+            client_ephemeral: auth.client_ephemeral,
+            expected_server_proof: auth.expected_server_proof,
+        })
+    }
+}
+
+#[cfg(feature = "srp-custom")]
+static SRP_PROVIDER: OnceLock<Box<dyn SrpProvider>> = OnceLock::new();
+
+/// Set the [`SrpProvider`] used by the logins, e.g. a pure Rust implementation when building
+/// without the `srp-go` feature. It can only be set once, otherwise `provider` is returned.
+#[cfg(feature = "srp-custom")]
+pub fn set_srp_provider(provider: Box<dyn SrpProvider>) -> Result<(), Box<dyn SrpProvider>> {
+    SRP_PROVIDER.set(provider)
+}
+
+/// Provider to use for the logins: the one set with `set_srp_provider`, otherwise go-srp if
+/// available.
+pub(crate) fn srp_provider() -> Option<&'static dyn SrpProvider> {
+    #[cfg(feature = "srp-custom")]
+    if let Some(provider) = SRP_PROVIDER.get() {
+        return Some(provider.as_ref());
+    }
+
+    default_srp_provider()
+}
+
+#[cfg(feature = "srp-go")]
+fn default_srp_provider() -> Option<&'static dyn SrpProvider> {
+    Some(&GoSrpProvider)
+}
+
+#[cfg(not(feature = "srp-go"))]
+fn default_srp_provider() -> Option<&'static dyn SrpProvider> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_srp_provider() {
+        struct Fixed;
+
+        impl SrpProvider for Fixed {
+            fn generate(
+                &self,
+                _: &str,
+                password: &str,
+                _: i64,
+                _: &str,
+                _: &str,
+                _: &str,
+            ) -> Result<SrpProofs, SrpError> {
+                if password.is_empty() {
+                    return Err(SrpError::Proof("empty password".to_string()));
+                }
+                Ok(SrpProofs {
+                    client_proof: "proof".to_string(),
+                    client_ephemeral: "ephemeral".to_string(),
+                    expected_server_proof: "server_proof".to_string(),
+                })
+            }
+        }
+
+        let provider: &dyn SrpProvider = &Fixed;
+        let proofs = provider
+            .generate("user", "password", 4, "", "", "")
+            .unwrap();
+        assert_eq!(proofs.client_proof, "proof");
+        assert_eq!(
+            provider.generate("user", "", 4, "", "", "").unwrap_err(),
+            SrpError::Proof("empty password".to_string())
+        );
+    }
+}
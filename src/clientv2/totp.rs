@@ -1,22 +1,99 @@
-use crate::clientv2::Session;
-use crate::http;
-use crate::http::Sequence;
+use crate::clientv2::{LoginError, Session};
+use crate::domain::Fido2AuthenticationOptions;
+#[cfg(not(feature = "async-traits"))]
+use crate::http::{BoxFuture, MaybeSend};
+use crate::http::{ClientAsync, ClientSync, Sequence};
+use std::sync::atomic::Ordering;
 
-#[derive(Debug)]
-pub struct TotpSession(pub(super) Session);
+#[derive(Debug, Clone)]
+pub struct TotpSession(
+    pub(super) Session,
+    pub(super) Option<Fido2AuthenticationOptions>,
+);
 
 impl TotpSession {
+    /// Options of the user's FIDO2 keys, if the account also accepts them as second factor. This
+    /// crate can't submit FIDO2 assertions yet, the login still has to be completed with
+    /// [`TotpSession::submit_totp`].
+    pub fn fido2_options(&self) -> Option<&Fido2AuthenticationOptions> {
+        self.1.as_ref()
+    }
+
+    /// Submit the TOTP code to complete the login. Fails with
+    /// [`LoginError::TwoFactorNotRequested`] without contacting the server if the login was
+    /// already completed, e.g. through a clone of this session.
     pub fn submit_totp<'a>(
         &'a self,
         code: &'a str,
-    ) -> impl Sequence<Output = Session, Error = http::Error> + 'a {
-        let auth = self.0.user_auth.clone();
-        self.0
-            .submit_totp(code)
-            .map(move |_| Ok(Session { user_auth: auth }))
+    ) -> impl Sequence<Output = Session, Error = LoginError> + 'a {
+        SubmitTotpSequence {
+            session: &self.0,
+            code,
+        }
     }
 
     pub fn logout(&self) -> impl Sequence<Output = ()> + '_ {
         self.0.logout()
     }
 }
+
+struct SubmitTotpSequence<'a> {
+    session: &'a Session,
+    code: &'a str,
+}
+
+impl<'a> SubmitTotpSequence<'a> {
+    fn check_pending(&self) -> Result<(), LoginError> {
+        if self.session.totp_pending.load(Ordering::SeqCst) {
+            Ok(())
+        } else {
+            Err(LoginError::TwoFactorNotRequested)
+        }
+    }
+
+    fn complete(&self) -> Session {
+        self.session.totp_pending.store(false, Ordering::SeqCst);
+        self.session.clone()
+    }
+}
+
+impl<'s> Sequence for SubmitTotpSequence<'s> {
+    type Output = Session;
+    type Error = LoginError;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        self.check_pending()?;
+        self.session.submit_totp(self.code).do_sync(client)?;
+        Ok(self.complete())
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> BoxFuture<'a, Result<Self::Output, Self::Error>>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(async move {
+            self.check_pending()?;
+            self.session.submit_totp(self.code).do_async(client).await?;
+            Ok(self.complete())
+        })
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl std::future::Future<Output = Result<Self::Output, Self::Error>> + 'a
+    where
+        Self: 'a,
+    {
+        async move {
+            self.check_pending()?;
+            self.session.submit_totp(self.code).do_async(client).await?;
+            Ok(self.complete())
+        }
+    }
+}
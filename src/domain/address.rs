@@ -0,0 +1,36 @@
+use crate::domain::Key;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+
+/// Address API ID.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+pub struct AddressId(pub String);
+
+impl Display for AddressId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Represents one of the user's email addresses.
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct Address {
+    #[serde(rename = "ID")]
+    pub id: AddressId,
+    pub email: String,
+    #[serde(default)]
+    pub display_name: String,
+    /// 0 if the address is disabled, 1 if it is enabled.
+    #[serde(default)]
+    pub status: i64,
+    /// Kind of address, e.g. original, alias or custom domain.
+    #[serde(rename = "Type", default)]
+    pub address_type: i64,
+    #[serde(default)]
+    pub order: i64,
+    #[serde(default)]
+    pub keys: Vec<Key>,
+}
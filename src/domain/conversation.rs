@@ -0,0 +1,99 @@
+use crate::domain::{LabelId, MessageAddress};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Conversation API ID.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+pub struct ConversationId(String);
+
+impl Display for ConversationId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for ConversationId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
+/// Per label statistics of a conversation.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct ConversationLabel {
+    #[serde(rename = "ID")]
+    pub id: LabelId,
+    #[serde(default)]
+    pub context_num_messages: i64,
+    #[serde(default)]
+    pub context_num_unread: i64,
+    #[serde(default)]
+    pub context_time: i64,
+    #[serde(default)]
+    pub context_size: i64,
+    #[serde(default)]
+    pub context_num_attachments: i64,
+}
+
+/// Group of messages belonging to the same thread.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct Conversation {
+    #[serde(rename = "ID")]
+    pub id: ConversationId,
+    #[serde(default)]
+    pub order: i64,
+    pub subject: String,
+    #[serde(default)]
+    pub senders: Vec<MessageAddress>,
+    #[serde(default)]
+    pub recipients: Vec<MessageAddress>,
+    pub num_messages: i64,
+    pub num_unread: i64,
+    #[serde(default)]
+    pub num_attachments: i64,
+    /// Unix timestamp of the latest message.
+    #[serde(default)]
+    pub time: i64,
+    /// Size of all the messages in bytes.
+    #[serde(default)]
+    pub size: i64,
+    #[serde(default)]
+    pub labels: Vec<ConversationLabel>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversation_deserialize() {
+        let json = r#"{
+            "ID": "conversation_id",
+            "Order": 100,
+            "Subject": "Hello",
+            "Senders": [{"Name": "Foo", "Address": "foo@bar.com"}],
+            "NumMessages": 3,
+            "NumUnread": 1,
+            "Time": 1680000000,
+            "Labels": [{"ID": "0", "ContextNumMessages": 2, "ContextNumUnread": 1}]
+        }"#;
+
+        let conversation = serde_json::from_str::<Conversation>(json).unwrap();
+        assert_eq!(conversation.num_messages, 3);
+        assert_eq!(conversation.num_unread, 1);
+        assert_eq!(conversation.time, 1680000000);
+        assert_eq!(conversation.labels.len(), 1);
+        assert_eq!(conversation.labels[0].id, LabelId::inbox());
+        assert_eq!(conversation.labels[0].context_num_messages, 2);
+        assert!(conversation.recipients.is_empty());
+    }
+}
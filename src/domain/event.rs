@@ -1,10 +1,18 @@
-use crate::domain::{Boolean, Label, LabelId};
-use serde::Deserialize;
+use crate::domain::{Address, AddressId, Attachment, Boolean, Label, LabelCount, LabelId};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
 use serde_repr::Deserialize_repr;
+use std::convert::Infallible;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 /// Id for an API Event.
+///
+/// Ids are ordered lexically so they can be sorted or used as keys of ordered maps. The ids are
+/// opaque to clients, so this order does not tell whether an event is newer than another one.
+/// Follow the event stream with [`Event::more`] and [`Event::has_changes_since`] instead.
 pub struct EventId(pub String);
 
 impl Display for EventId {
@@ -13,34 +21,323 @@ impl Display for EventId {
     }
 }
 
-#[derive(Debug, Deserialize_repr, Eq, PartialEq, Copy, Clone)]
+impl FromStr for EventId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
+#[derive(Debug, Deserialize_repr, Eq, PartialEq, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde-serialize", derive(serde_repr::Serialize_repr))]
 #[repr(u8)]
 pub enum MoreEvents {
+    #[default]
     No = 0,
     Yes = 1,
 }
 
+/// Changes since a previous event. Fields of the event which are not modelled here are ignored.
+///
+/// Items of the change lists which fail to parse are left out instead of failing the whole
+/// event, so that a single malformed item can't keep the cursor from advancing. They are listed
+/// in [`Event::skipped_items`].
 #[derive(Debug, Deserialize)]
-#[serde(rename_all = "PascalCase")]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase", from = "RawEvent")]
 pub struct Event {
     #[serde(rename = "EventID")]
     pub event_id: EventId,
     pub more: MoreEvents,
     pub messages: Option<Vec<MessageEvent>>,
     pub labels: Option<Vec<LabelEvent>>,
+    pub addresses: Option<Vec<AddressEvent>>,
+    /// Updated message counts of the labels which changed.
+    pub message_counts: Option<Vec<LabelCount>>,
+    #[serde(default)]
+    pub refresh: RefreshFlags,
+    /// Parse errors of the items which were left out, as `<List>[<index>]: <error>`.
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    pub skipped_items: Vec<String>,
 }
 
-#[derive(Debug, Deserialize_repr, Eq, PartialEq, Copy, Clone)]
-#[repr(u8)]
+/// [`Event`] with its change lists not yet parsed.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawEvent {
+    #[serde(rename = "EventID")]
+    event_id: EventId,
+    more: MoreEvents,
+    messages: Option<Vec<serde_json::Value>>,
+    labels: Option<Vec<serde_json::Value>>,
+    addresses: Option<Vec<serde_json::Value>>,
+    message_counts: Option<Vec<serde_json::Value>>,
+    #[serde(default)]
+    refresh: RefreshFlags,
+}
+
+impl From<RawEvent> for Event {
+    fn from(raw: RawEvent) -> Self {
+        let mut skipped_items = Vec::new();
+        Self {
+            event_id: raw.event_id,
+            more: raw.more,
+            messages: parse_event_items("Messages", raw.messages, &mut skipped_items),
+            labels: parse_event_items("Labels", raw.labels, &mut skipped_items),
+            addresses: parse_event_items("Addresses", raw.addresses, &mut skipped_items),
+            message_counts: parse_event_items(
+                "MessageCounts",
+                raw.message_counts,
+                &mut skipped_items,
+            ),
+            refresh: raw.refresh,
+            skipped_items,
+        }
+    }
+}
+
+/// Parse the `items` of the event list `name`, leaving out and recording the ones which fail.
+fn parse_event_items<T: DeserializeOwned>(
+    name: &str,
+    items: Option<Vec<serde_json::Value>>,
+    skipped: &mut Vec<String>,
+) -> Option<Vec<T>> {
+    let items = items?
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, item)| match serde_json::from_value(item) {
+            Ok(item) => Some(item),
+            Err(e) => {
+                let error = format!("{name}[{index}]: {e}");
+                log::warn!("Skipping malformed event item {error}");
+                skipped.push(error);
+                None
+            }
+        })
+        .collect();
+    Some(items)
+}
+
+impl Event {
+    /// Whether the server asked for the local state to be discarded and fetched again from
+    /// scratch, usually because the event cursor is too old.
+    pub fn needs_full_resync(&self) -> bool {
+        !self.refresh.is_empty()
+    }
+
+    /// Whether this event, fetched from the cursor `after`, carries any changes or asks for a
+    /// full resync.
+    pub fn has_changes_since(&self, after: &EventId) -> bool {
+        self.event_id != *after
+            || self.needs_full_resync()
+            || self.messages.as_ref().is_some_and(|m| !m.is_empty())
+            || self.labels.as_ref().is_some_and(|l| !l.is_empty())
+            || self.addresses.as_ref().is_some_and(|a| !a.is_empty())
+            || self.message_counts.as_ref().is_some_and(|c| !c.is_empty())
+    }
+
+    pub fn message_events(&self) -> impl Iterator<Item = &MessageEvent> {
+        self.messages.iter().flatten()
+    }
+
+    pub fn label_events(&self) -> impl Iterator<Item = &LabelEvent> {
+        self.labels.iter().flatten()
+    }
+
+    pub fn address_events(&self) -> impl Iterator<Item = &AddressEvent> {
+        self.addresses.iter().flatten()
+    }
+}
+
+/// Message changes accumulated over one or more events.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EventDelta {
+    /// Event id from which to continue polling.
+    pub new_cursor: EventId,
+    pub created: Vec<MessageId>,
+    pub updated: Vec<MessageId>,
+    pub deleted: Vec<MessageId>,
+    /// Local state should be discarded and fetched again, see [`Event::needs_full_resync`].
+    pub needs_resync: bool,
+    /// More events are available after [`EventDelta::new_cursor`].
+    pub more: bool,
+    /// Latest message counts of the labels which changed.
+    pub counts: Vec<LabelCount>,
+}
+
+impl EventDelta {
+    pub fn new(cursor: EventId) -> Self {
+        Self {
+            new_cursor: cursor,
+            created: Vec::new(),
+            updated: Vec::new(),
+            deleted: Vec::new(),
+            needs_resync: false,
+            more: false,
+            counts: Vec::new(),
+        }
+    }
+
+    /// Fold the message changes of `event` into this delta. Actions are applied in order, later
+    /// actions superseding earlier ones, so that every message ends up in at most one of
+    /// [`EventDelta::created`], [`EventDelta::updated`] and [`EventDelta::deleted`]:
+    ///
+    /// * Messages created and deleted within the delta are dropped entirely.
+    /// * Updates to created messages are reported as created.
+    /// * Creating or updating a deleted message cancels the deletion.
+    ///
+    /// Unknown actions are reported as updates. Each list keeps the order in which messages were
+    /// first added to it.
+    pub fn apply(&mut self, event: &Event) {
+        self.new_cursor = event.event_id.clone();
+        self.needs_resync |= event.needs_full_resync();
+        self.more = event.more == MoreEvents::Yes;
+
+        for count in event.message_counts.iter().flatten() {
+            self.counts.retain(|c| c.label_id != count.label_id);
+            self.counts.push(count.clone());
+        }
+
+        for message in event.message_events() {
+            let id = &message.id;
+            match message.action {
+                EventAction::Create => {
+                    self.deleted.retain(|v| v != id);
+                    self.updated.retain(|v| v != id);
+                    push_unique(&mut self.created, id);
+                }
+                EventAction::Update | EventAction::UpdateFlags | EventAction::Unknown(_) => {
+                    if !self.created.contains(id) {
+                        self.deleted.retain(|v| v != id);
+                        push_unique(&mut self.updated, id);
+                    }
+                }
+                EventAction::Delete => {
+                    self.updated.retain(|v| v != id);
+                    let len = self.created.len();
+                    self.created.retain(|v| v != id);
+                    if len == self.created.len() {
+                        push_unique(&mut self.deleted, id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn push_unique(ids: &mut Vec<MessageId>, id: &MessageId) {
+    if !ids.contains(id) {
+        ids.push(id.clone());
+    }
+}
+
+/// Latest event of the account, from which [`Event`]s can be polled.
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct LatestEvent {
+    #[serde(rename = "EventID")]
+    pub event_id: EventId,
+    /// More events are available right away after [`LatestEvent::event_id`].
+    #[serde(default)]
+    pub more: MoreEvents,
+    #[serde(default)]
+    pub refresh: RefreshFlags,
+}
+
+impl LatestEvent {
+    /// Whether the local state must be fetched again from scratch before following the events,
+    /// see [`Event::needs_full_resync`]. Saves fetching the first event to find out.
+    pub fn needs_full_resync(&self) -> bool {
+        !self.refresh.is_empty()
+    }
+}
+
+/// Bitmask of the `Refresh` field of an [`Event`], telling which parts of the local state are
+/// no longer valid.
+#[derive(Debug, Deserialize, Default, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(transparent)]
+pub struct RefreshFlags(pub u8);
+
+impl RefreshFlags {
+    pub const NONE: RefreshFlags = RefreshFlags(0);
+    pub const MAIL: RefreshFlags = RefreshFlags(1);
+    pub const CONTACTS: RefreshFlags = RefreshFlags(1 << 1);
+    pub const ALL: RefreshFlags = RefreshFlags(u8::MAX);
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Check whether all the bits of `other` are set.
+    pub fn contains(&self, other: RefreshFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for RefreshFlags {
+    type Output = RefreshFlags;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        RefreshFlags(self.0 | rhs.0)
+    }
+}
+
+/// Action of a message or label event. Actions not known to this crate are kept as
+/// [`EventAction::Unknown`] rather than failing to parse the whole event.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize),
+    serde(into = "u8")
+)]
 pub enum EventAction {
-    Delete = 0,
-    Create = 1,
-    Update = 2,
-    UpdateFlags = 3,
+    Delete,
+    Create,
+    Update,
+    UpdateFlags,
+    Unknown(u8),
+}
+
+impl From<u8> for EventAction {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Delete,
+            1 => Self::Create,
+            2 => Self::Update,
+            3 => Self::UpdateFlags,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+impl From<EventAction> for u8 {
+    fn from(value: EventAction) -> Self {
+        match value {
+            EventAction::Delete => 0,
+            EventAction::Create => 1,
+            EventAction::Update => 2,
+            EventAction::UpdateFlags => 3,
+            EventAction::Unknown(v) => v,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for EventAction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let action = Self::from(u8::deserialize(deserializer)?);
+        if let Self::Unknown(v) = action {
+            log::warn!("Unknown event action {v}");
+        }
+        Ok(action)
+    }
 }
 
 /// Message API ID.
 #[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 pub struct MessageId(String);
 
 impl Display for MessageId {
@@ -49,8 +346,17 @@ impl Display for MessageId {
     }
 }
 
+impl FromStr for MessageId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
 /// Event data related to a Message event.
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
 pub struct MessageEvent {
     #[serde(rename = "ID")]
@@ -59,22 +365,62 @@ pub struct MessageEvent {
     pub message: Option<Message>,
 }
 
+impl MessageEvent {
+    /// Labels which were added to and removed from the message, compared to `prev`, its
+    /// previously known state. Returns `None` if the event does not carry the message, e.g. for
+    /// [`EventAction::Delete`].
+    pub fn label_diff(&self, prev: &Message) -> Option<(Vec<LabelId>, Vec<LabelId>)> {
+        let message = self.message.as_ref()?;
+        let added = message
+            .labels
+            .iter()
+            .filter(|l| !prev.labels.contains(l))
+            .cloned()
+            .collect();
+        let removed = prev
+            .labels
+            .iter()
+            .filter(|l| !message.labels.contains(l))
+            .cloned()
+            .collect();
+        Some((added, removed))
+    }
+}
+
 /// Represents an email message.
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
 pub struct Message {
     #[serde(rename = "ID")]
     pub id: MessageId,
+    /// Complete list of the labels of the message. Also present when the message of an
+    /// [`EventAction::Update`] or [`EventAction::UpdateFlags`] event is partial, see
+    /// [`MessageEvent::label_diff`].
     #[serde(rename = "LabelIDs")]
     pub labels: Vec<LabelId>,
     pub subject: String,
     pub sender_address: String,
     pub sender_name: Option<String>,
     pub unread: Boolean,
+    /// Unix timestamp of the message.
+    #[serde(default)]
+    pub time: i64,
+    /// Size of the message in bytes.
+    #[serde(default)]
+    pub size: i64,
+    #[serde(default)]
+    pub num_attachments: i64,
+    #[serde(default)]
+    pub flags: i64,
+    /// Only set when fetching a single message.
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
 }
 
 /// Event data related to a Label event
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
 pub struct LabelEvent {
     #[serde(rename = "ID")]
@@ -82,3 +428,382 @@ pub struct LabelEvent {
     pub action: EventAction,
     pub label: Option<Label>,
 }
+
+/// Event data related to an Address event
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct AddressEvent {
+    #[serde(rename = "ID")]
+    pub id: AddressId,
+    pub action: EventAction,
+    pub address: Option<Address>,
+}
+
+/// Apply `event` to the cached `addresses`: deleted addresses are removed, created or updated
+/// ones are inserted or replaced with the address carried by the event. The list is kept sorted
+/// by [`Address::order`], so that reordering the addresses, which updates each of them, is
+/// reflected as well.
+pub fn apply_address_event(addresses: &mut Vec<Address>, event: &AddressEvent) {
+    match (event.action, &event.address) {
+        (EventAction::Delete, _) => addresses.retain(|a| a.id != event.id),
+        (_, Some(address)) => match addresses.iter_mut().find(|a| a.id == event.id) {
+            Some(existing) => *existing = address.clone(),
+            None => addresses.push(address.clone()),
+        },
+        (_, None) => return,
+    }
+
+    addresses.sort_by_key(|a| a.order);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Event `id` with the extra json `fields`, e.g. `"Labels": []`.
+    fn event(id: &str, fields: &str) -> Event {
+        let fields = if fields.is_empty() {
+            String::new()
+        } else {
+            format!(", {fields}")
+        };
+        serde_json::from_str(&format!(r#"{{"EventID": "{id}", "More": 0{fields}}}"#)).unwrap()
+    }
+
+    /// `Messages` field of an event with the `(id, action)` pairs of `messages`.
+    fn messages(messages: &[(&str, u8)]) -> String {
+        let messages = messages
+            .iter()
+            .map(|(id, action)| format!(r#"{{"ID": "{id}", "Action": {action}}}"#))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(r#""Messages": [{messages}]"#)
+    }
+
+    #[cfg(feature = "serde-serialize")]
+    #[test]
+    fn event_serialize_round_trip() {
+        let json = r#"{
+            "EventID": "event_id",
+            "More": 1,
+            "Messages": [{
+                "ID": "message_id",
+                "Action": 1,
+                "Message": {
+                    "ID": "message_id",
+                    "LabelIDs": ["0", "5"],
+                    "Subject": "Subject",
+                    "SenderAddress": "foo@bar.com",
+                    "SenderName": null,
+                    "Unread": 1
+                }
+            }],
+            "Labels": null
+        }"#;
+
+        let event = serde_json::from_str::<Event>(json).unwrap();
+        let serialized = serde_json::to_string(&event).unwrap();
+        let round_trip = serde_json::from_str::<Event>(&serialized).unwrap();
+
+        assert_eq!(event.event_id, round_trip.event_id);
+        assert_eq!(MoreEvents::Yes, round_trip.more);
+        let message = round_trip.messages.unwrap().remove(0);
+        assert_eq!(EventAction::Create, message.action);
+        assert_eq!(Boolean::True, message.message.unwrap().unread);
+    }
+
+    #[test]
+    fn event_unknown_action() {
+        let json = r##"{
+            "EventID": "event_id",
+            "More": 0,
+            "Messages": [{
+                "ID": "message_id",
+                "Action": 42
+            }],
+            "Labels": [{
+                "ID": "label_id",
+                "Action": 1,
+                "Label": {
+                    "ID": "label_id",
+                    "Name": "label",
+                    "Path": "label",
+                    "Color": "#7272a7",
+                    "Type": 42
+                }
+            }]
+        }"##;
+
+        let event = serde_json::from_str::<Event>(json).unwrap();
+        let message = event.messages.unwrap().remove(0);
+        assert_eq!(EventAction::Unknown(42), message.action);
+        let label = event.labels.unwrap().remove(0).label.unwrap();
+        assert_eq!(crate::domain::LabelType::Unknown(42), label.label_type);
+    }
+
+    #[test]
+    fn event_address_events() {
+        let json = r#"{
+            "EventID": "event_id",
+            "More": 0,
+            "Addresses": [{
+                "ID": "address_id",
+                "Action": 1,
+                "Address": {
+                    "ID": "address_id",
+                    "Email": "foo@bar.com",
+                    "Status": 1,
+                    "Type": 1,
+                    "Keys": []
+                }
+            }, {
+                "ID": "other_address_id",
+                "Action": 0
+            }],
+            "MessageCounts": [],
+            "UsedSpace": 1024
+        }"#;
+
+        let event = serde_json::from_str::<Event>(json).unwrap();
+        assert!(!event.needs_full_resync());
+        assert_eq!(0, event.message_events().count());
+        assert_eq!(0, event.label_events().count());
+
+        let addresses = event.address_events().collect::<Vec<_>>();
+        assert_eq!(2, addresses.len());
+        assert_eq!(EventAction::Create, addresses[0].action);
+        assert_eq!(
+            "foo@bar.com",
+            addresses[0].address.as_ref().unwrap().email.as_str()
+        );
+        assert_eq!(EventAction::Delete, addresses[1].action);
+        assert!(addresses[1].address.is_none());
+    }
+
+    #[test]
+    fn event_refresh_flags() {
+        let event = event("event_id", r#""Refresh": 1"#);
+        assert!(event.needs_full_resync());
+        assert!(event.refresh.contains(RefreshFlags::MAIL));
+        assert!(!event.refresh.contains(RefreshFlags::CONTACTS));
+        assert!(RefreshFlags::ALL.contains(RefreshFlags::MAIL | RefreshFlags::CONTACTS));
+    }
+
+    #[test]
+    fn message_event_label_diff() {
+        fn message(labels: &str) -> Message {
+            serde_json::from_str(&format!(
+                r#"{{
+                    "ID": "message_id",
+                    "LabelIDs": {labels},
+                    "Subject": "subject",
+                    "SenderAddress": "sender@proton.me",
+                    "SenderName": null,
+                    "Unread": 0
+                }}"#
+            ))
+            .unwrap()
+        }
+
+        let event = MessageEvent {
+            id: MessageId("message_id".to_string()),
+            action: EventAction::UpdateFlags,
+            message: Some(message(r#"["0", "10"]"#)),
+        };
+        let (added, removed) = event.label_diff(&message(r#"["0", "5"]"#)).unwrap();
+        assert_eq!(added, vec![LabelId("10".to_string())]);
+        assert_eq!(removed, vec![LabelId("5".to_string())]);
+
+        let event = MessageEvent {
+            id: MessageId("message_id".to_string()),
+            action: EventAction::Delete,
+            message: None,
+        };
+        assert!(event.label_diff(&message("[]")).is_none());
+    }
+
+    #[test]
+    fn address_event_reconciliation() {
+        fn address_event(id: &str, action: u8, order: Option<i64>) -> AddressEvent {
+            let address = order.map(|order| {
+                serde_json::json!({"ID": id, "Email": format!("{id}@proton.me"), "Order": order})
+            });
+            serde_json::from_value(
+                serde_json::json!({"ID": id, "Action": action, "Address": address}),
+            )
+            .unwrap()
+        }
+        fn ids(addresses: &[Address]) -> Vec<&str> {
+            addresses.iter().map(|a| a.id.0.as_str()).collect()
+        }
+
+        let mut addresses = Vec::new();
+        apply_address_event(&mut addresses, &address_event("a", 1, Some(1)));
+        apply_address_event(&mut addresses, &address_event("b", 1, Some(2)));
+        assert_eq!(ids(&addresses), ["a", "b"]);
+
+        // Reorder, which the server reports as an update of each address.
+        apply_address_event(&mut addresses, &address_event("b", 2, Some(1)));
+        apply_address_event(&mut addresses, &address_event("a", 2, Some(2)));
+        assert_eq!(ids(&addresses), ["b", "a"]);
+        assert_eq!(addresses[0].order, 1);
+
+        apply_address_event(&mut addresses, &address_event("a", 2, None));
+        assert_eq!(ids(&addresses), ["b", "a"]);
+
+        apply_address_event(&mut addresses, &address_event("b", 0, None));
+        assert_eq!(ids(&addresses), ["a"]);
+    }
+
+    #[test]
+    fn event_has_changes_since() {
+        let cursor = EventId("event_id".to_string());
+        assert!(!event("event_id", "").has_changes_since(&cursor));
+        assert!(!event("event_id", r#""Labels": []"#).has_changes_since(&cursor));
+        assert!(event("next_id", "").has_changes_since(&cursor));
+
+        for changes in [
+            r#""Refresh": 1"#,
+            r#""Addresses": [{"ID": "a", "Action": 0}]"#,
+            r#""MessageCounts": [{"LabelID": "0", "Total": 1, "Unread": 1}]"#,
+        ] {
+            let event = event("event_id", changes);
+            assert!(event.skipped_items.is_empty(), "{:?}", event.skipped_items);
+            assert!(event.has_changes_since(&cursor), "{changes}");
+        }
+    }
+
+    #[test]
+    fn event_delta_fold() {
+        let id = |v: &str| v.parse::<MessageId>().unwrap();
+
+        let mut delta = EventDelta::new("0".parse().unwrap());
+        delta.apply(&event(
+            "1",
+            &messages(&[("a", 1), ("b", 2), ("c", 1), ("d", 2)]),
+        ));
+        delta.apply(&event(
+            "2",
+            &messages(&[("a", 2), ("c", 0), ("d", 0), ("e", 3)]),
+        ));
+
+        assert_eq!(delta.new_cursor, "2".parse().unwrap());
+        assert_eq!(delta.created, vec![id("a")]);
+        assert_eq!(delta.updated, vec![id("b"), id("e")]);
+        assert_eq!(delta.deleted, vec![id("d")]);
+        assert!(!delta.needs_resync);
+        assert!(!delta.more);
+    }
+
+    #[test]
+    fn event_delta_fold_within_batch() {
+        let id = |v: &str| v.parse::<MessageId>().unwrap();
+        let fold = |items: &[(&str, u8)]| {
+            let mut delta = EventDelta::new("0".parse().unwrap());
+            delta.apply(&event("1", &messages(items)));
+            (delta.created, delta.updated, delta.deleted)
+        };
+
+        // Create then delete.
+        assert_eq!(fold(&[("a", 1), ("a", 0)]), (vec![], vec![], vec![]));
+        // Create then update.
+        assert_eq!(
+            fold(&[("a", 1), ("a", 2), ("a", 3)]),
+            (vec![id("a")], vec![], vec![])
+        );
+        // Update then create.
+        assert_eq!(fold(&[("a", 2), ("a", 1)]), (vec![id("a")], vec![], vec![]));
+        // Update then delete.
+        assert_eq!(fold(&[("a", 2), ("a", 0)]), (vec![], vec![], vec![id("a")]));
+        // Delete then update or create.
+        assert_eq!(fold(&[("a", 0), ("a", 2)]), (vec![], vec![id("a")], vec![]));
+        assert_eq!(fold(&[("a", 0), ("a", 1)]), (vec![id("a")], vec![], vec![]));
+        // Repeated actions are reported once, in order of first appearance.
+        assert_eq!(
+            fold(&[("b", 2), ("a", 2), ("b", 3), ("c", 0), ("c", 0)]),
+            (vec![], vec![id("b"), id("a")], vec![id("c")])
+        );
+    }
+
+    #[test]
+    fn event_delta_message_counts() {
+        let counts = |id: &str, unread: i64| -> Event {
+            event(
+                id,
+                &format!(
+                    r#""MessageCounts": [
+                        {{"LabelID": "0", "Total": 10, "Unread": {unread}}},
+                        {{"LabelID": "{id}", "Total": 1, "Unread": 0}}
+                    ]"#
+                ),
+            )
+        };
+
+        let mut delta = EventDelta::new("0".parse().unwrap());
+        delta.apply(&counts("1", 5));
+        delta.apply(&counts("2", 3));
+
+        assert_eq!(delta.counts.len(), 3);
+        let inbox = delta
+            .counts
+            .iter()
+            .find(|c| c.label_id == LabelId::inbox())
+            .unwrap();
+        assert_eq!(inbox.total, 10);
+        assert_eq!(inbox.unread, 3);
+    }
+
+    #[test]
+    fn ids_from_str_round_trip() {
+        let event_id = "event_id".parse::<EventId>().unwrap();
+        assert_eq!(event_id.to_string(), "event_id");
+
+        let message_id = "message_id".parse::<MessageId>().unwrap();
+        assert_eq!(message_id.to_string(), "message_id");
+
+        let label_id = "0".parse::<LabelId>().unwrap();
+        assert_eq!(label_id, LabelId::inbox());
+    }
+
+    #[test]
+    fn event_id_ordering_is_lexical() {
+        let mut ids = vec![
+            EventId("b".to_string()),
+            EventId("ab".to_string()),
+            EventId("a".to_string()),
+        ];
+        ids.sort();
+        assert_eq!(
+            ids,
+            vec![
+                EventId("a".to_string()),
+                EventId("ab".to_string()),
+                EventId("b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn event_skips_malformed_items() {
+        let event = serde_json::from_str::<Event>(
+            r#"{
+                "EventID": "next_id",
+                "More": 0,
+                "Messages": [
+                    {"ID": "bad", "Action": 1, "Message": {"ID": "bad"}},
+                    {"ID": "good", "Action": 0}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(event.event_id.0, "next_id");
+        let messages = event.messages.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id.to_string(), "good");
+        assert_eq!(event.skipped_items.len(), 1);
+        assert!(event.skipped_items[0].starts_with("Messages[0]: "));
+        assert!(event.labels.is_none());
+    }
+}
@@ -11,11 +11,13 @@ pub enum HumanVerificationType {
     /// User needs to solve a Captcha, use [`crate::captcha_get`] to retrieve the token, solve in a web
     /// browser/view and retrieve the token posted via an `HVCaptchaMessage`.
     Captcha,
-    /// User needs to verify via a token send via an email. Note: Request for this
-    /// verification is not yet implemented.
+    /// User needs to verify via a code sent via email, use
+    /// [`crate::human_verification_code_request`] to send the code and
+    /// [`HumanVerificationLoginData::from_code`] to submit it.
     Email,
-    /// User needs to verify via a token send via sms. Note: Request for this verification is not
-    /// yet inmplemented.
+    /// User needs to verify via a code sent via sms, use
+    /// [`crate::human_verification_code_request`] to send the code and
+    /// [`HumanVerificationLoginData::from_code`] to submit it.
     Sms,
 }
 
@@ -44,6 +46,16 @@ pub struct HumanVerificationLoginData {
     pub token: String,
 }
 
+impl HumanVerificationLoginData {
+    /// Create the login data for a `code` received via email or sms at `destination`.
+    pub fn from_code(hv_type: HumanVerificationType, destination: &str, code: &str) -> Self {
+        Self {
+            hv_type,
+            token: format!("{destination}:{code}"),
+        }
+    }
+}
+
 /// Information for the Human Verification request.
 #[derive(Debug)]
 pub struct HumanVerification {
@@ -1,11 +1,13 @@
 use crate::domain::Boolean;
-use serde::Deserialize;
-use serde_repr::Deserialize_repr;
+use serde::{Deserialize, Deserializer};
+use std::convert::Infallible;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 /// Labels API ID. Note that label IDs are used interchangeably between what we would consider
 /// mail labels and mailboxes.
 #[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 pub struct LabelId(pub String);
 
 impl Display for LabelId {
@@ -14,16 +16,77 @@ impl Display for LabelId {
     }
 }
 
-#[derive(Debug, Deserialize_repr, Eq, PartialEq, Copy, Clone)]
-#[repr(u8)]
+impl FromStr for LabelId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
+/// Type of a label. Types not known to this crate are kept as [`LabelType::Unknown`] rather
+/// than failing to parse the label.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize),
+    serde(into = "u8")
+)]
 pub enum LabelType {
-    Label = 1,
-    ContactGroup = 2,
-    Folder = 3,
-    System = 4,
+    Label,
+    ContactGroup,
+    Folder,
+    System,
+    Unknown(u8),
+}
+
+impl From<u8> for LabelType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::Label,
+            2 => Self::ContactGroup,
+            3 => Self::Folder,
+            4 => Self::System,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+impl From<LabelType> for u8 {
+    fn from(value: LabelType) -> Self {
+        match value {
+            LabelType::Label => 1,
+            LabelType::ContactGroup => 2,
+            LabelType::Folder => 3,
+            LabelType::System => 4,
+            LabelType::Unknown(v) => v,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LabelType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let label_type = Self::from(u8::deserialize(deserializer)?);
+        if let Self::Unknown(v) = label_type {
+            log::warn!("Unknown label type {v}");
+        }
+        Ok(label_type)
+    }
+}
+
+/// Number of messages with a given label.
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct LabelCount {
+    #[serde(rename = "LabelID")]
+    pub label_id: LabelId,
+    pub total: i64,
+    pub unread: i64,
 }
 
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
 pub struct Label {
     #[serde(rename = "ID")]
@@ -44,15 +107,16 @@ pub struct Label {
     #[serde(default)]
     pub expanded: Boolean,
     #[serde(default = "default_label_order")]
-    pub order: i32,
+    pub order: i64,
 }
 
-fn default_label_order() -> i32 {
+fn default_label_order() -> i64 {
     0
 }
 
 /// SysLabelID represents system label identifiers that are constant for every account.
 #[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 pub struct SysLabelId(&'static str);
 
 impl PartialEq<LabelId> for SysLabelId {
@@ -73,22 +137,60 @@ impl From<SysLabelId> for LabelId {
     }
 }
 
+impl TryFrom<&LabelId> for SysLabelId {
+    type Error = ();
+
+    /// Succeeds if `value` is one of the system label ids.
+    fn try_from(value: &LabelId) -> Result<Self, Self::Error> {
+        SysLabelId::ALL
+            .iter()
+            .find(|id| *id == value)
+            .copied()
+            .ok_or(())
+    }
+}
+
 impl SysLabelId {
     pub const INBOX: SysLabelId = SysLabelId("0");
     pub const ALL_DRAFTS: SysLabelId = SysLabelId("1");
-    pub const ALL_SENT: SysLabelId = SysLabelId("1");
+    pub const ALL_SENT: SysLabelId = SysLabelId("2");
     pub const TRASH: SysLabelId = SysLabelId("3");
     pub const SPAM: SysLabelId = SysLabelId("4");
     pub const ALL_MAIL: SysLabelId = SysLabelId("5");
-    pub const ARCHIVE: SysLabelId = SysLabelId("5");
+    pub const ARCHIVE: SysLabelId = SysLabelId("6");
     pub const SENT: SysLabelId = SysLabelId("7");
     pub const DRAFTS: SysLabelId = SysLabelId("8");
     pub const OUTBOX: SysLabelId = SysLabelId("9");
     pub const STARRED: SysLabelId = SysLabelId("10");
     pub const ALL_SCHEDULED: SysLabelId = SysLabelId("12");
+
+    /// All the system labels.
+    pub const ALL: [SysLabelId; 12] = [
+        Self::INBOX,
+        Self::ALL_DRAFTS,
+        Self::ALL_SENT,
+        Self::TRASH,
+        Self::SPAM,
+        Self::ALL_MAIL,
+        Self::ARCHIVE,
+        Self::SENT,
+        Self::DRAFTS,
+        Self::OUTBOX,
+        Self::STARRED,
+        Self::ALL_SCHEDULED,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
 }
 
 impl LabelId {
+    /// Whether this is one of the system labels, see [`SysLabelId`].
+    pub fn is_system(&self) -> bool {
+        SysLabelId::try_from(self).is_ok()
+    }
+
     pub fn inbox() -> Self {
         SysLabelId::INBOX.into()
     }
@@ -143,3 +245,20 @@ impl Display for SysLabelId {
         self.0.fmt(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sys_label_id_conversion() {
+        let id = LabelId("6".to_string());
+        assert_eq!(SysLabelId::try_from(&id), Ok(SysLabelId::ARCHIVE));
+        assert_eq!(LabelId::from(SysLabelId::ARCHIVE), id);
+        assert!(id.is_system());
+
+        let id = LabelId("custom-label-id".to_string());
+        assert!(SysLabelId::try_from(&id).is_err());
+        assert!(!id.is_system());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::domain::{ConversationId, LabelId};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Attachment API ID.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+pub struct AttachmentId(pub String);
+
+impl Display for AttachmentId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for AttachmentId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
+/// Metadata of a message attachment.
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct Attachment {
+    #[serde(rename = "ID")]
+    pub id: AttachmentId,
+    pub name: String,
+    /// Size of the encrypted attachment data in bytes.
+    pub size: i64,
+    #[serde(rename = "MIMEType")]
+    pub mime_type: String,
+    /// Base64 encoded key packets required to decrypt the attachment data.
+    pub key_packets: String,
+}
+
+/// Email address with optional display name as used by the message API.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MessageAddress {
+    pub name: String,
+    pub address: String,
+}
+
+/// Template for a new draft message.
+///
+/// Note: The body is submitted as is, encrypting it is the responsibility of the caller.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DraftTemplate {
+    pub subject: String,
+    #[serde(rename = "ToList")]
+    pub to: Vec<MessageAddress>,
+    #[serde(rename = "CCList")]
+    pub cc: Vec<MessageAddress>,
+    #[serde(rename = "BCCList")]
+    pub bcc: Vec<MessageAddress>,
+    pub body: String,
+}
+
+/// Pagination and label filter for the listing endpoints, e.g. conversations.
+#[derive(Debug, Clone, Default)]
+pub struct PageFilter {
+    /// Only include items with this label.
+    pub label_id: Option<LabelId>,
+    /// Zero based page index.
+    pub page: u32,
+    /// Number of items per page, the server default is used if not set.
+    pub page_size: Option<u32>,
+}
+
+/// Filter for listing messages. Only the fields which are set are sent to the server.
+#[derive(Debug, Clone, Default)]
+pub struct MessageFilter {
+    pub page: PageFilter,
+    pub conversation_id: Option<ConversationId>,
+    /// Sender address.
+    pub from: Option<String>,
+    /// Recipient address.
+    pub to: Option<String>,
+    /// Search term matched against the message metadata.
+    pub keyword: Option<String>,
+    /// Only include messages sent at or after this unix timestamp, in seconds.
+    pub begin: Option<i64>,
+    /// Only include messages sent at or before this unix timestamp, in seconds.
+    pub end: Option<i64>,
+    pub unread: Option<bool>,
+    /// Only include messages with (`true`) or without (`false`) attachments.
+    pub attachments: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_with_attachments() {
+        let json = r#"{
+            "ID": "message_id",
+            "LabelIDs": ["0"],
+            "Subject": "Subject",
+            "SenderAddress": "foo@bar.com",
+            "SenderName": null,
+            "Unread": 0,
+            "Attachments": [{
+                "ID": "attachment_id",
+                "Name": "file.pdf",
+                "Size": 1024,
+                "MIMEType": "application/pdf",
+                "KeyPackets": "a2V5"
+            }]
+        }"#;
+
+        let message = serde_json::from_str::<crate::domain::Message>(json).unwrap();
+        assert_eq!(1, message.attachments.len());
+        let attachment = &message.attachments[0];
+        assert_eq!(AttachmentId("attachment_id".to_string()), attachment.id);
+        assert_eq!("file.pdf", attachment.name);
+        assert_eq!(1024, attachment.size);
+        assert_eq!("application/pdf", attachment.mime_type);
+    }
+}
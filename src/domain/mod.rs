@@ -1,13 +1,19 @@
 //! Domain Types.
 
+mod address;
+mod conversation;
 mod event;
 mod human_verification;
 mod labels;
+mod message;
 mod user;
 
+pub use address::*;
+pub use conversation::*;
 pub use event::*;
 pub use human_verification::*;
 pub use labels::*;
+pub use message::*;
 pub use user::*;
 
 use serde_repr::Deserialize_repr;
@@ -35,6 +41,7 @@ impl Display for TwoFactorAuth {
 }
 
 #[derive(Debug, Deserialize_repr, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde_repr::Serialize_repr))]
 #[repr(u8)]
 pub enum Boolean {
     False = 0,
@@ -46,3 +53,19 @@ impl Default for Boolean {
         Self::False
     }
 }
+
+impl From<Boolean> for bool {
+    fn from(value: Boolean) -> Self {
+        value == Boolean::True
+    }
+}
+
+impl From<bool> for Boolean {
+    fn from(value: bool) -> Self {
+        if value {
+            Boolean::True
+        } else {
+            Boolean::False
+        }
+    }
+}
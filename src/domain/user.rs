@@ -3,7 +3,10 @@ use serde_repr::Deserialize_repr;
 use std::fmt::{Display, Formatter};
 
 /// Represents an API User UID.
-#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+///
+/// The UID identifies the session, so its `Debug` output is redacted to keep it out of logs. Use
+/// `Display` or [`UserUid::as_str`] where the value is needed.
+#[derive(Deserialize, Eq, PartialEq, Hash, Clone)]
 pub struct UserUid(pub(crate) String);
 
 impl Display for UserUid {
@@ -12,6 +15,12 @@ impl Display for UserUid {
     }
 }
 
+impl std::fmt::Debug for UserUid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("UserUid([REDACTED])")
+    }
+}
+
 impl secrecy::Zeroize for UserUid {
     fn zeroize(&mut self) {
         self.0.zeroize()
@@ -34,8 +43,88 @@ impl<T: Into<String>> From<T> for UserUid {
     }
 }
 
+/// Information about one of the user's active sessions.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct SessionInfo {
+    #[serde(rename = "UID")]
+    pub uid: UserUid,
+    pub create_time: i64,
+    #[serde(rename = "ClientID")]
+    pub client_id: String,
+}
+
+/// Parameters sent along the refresh token when refreshing a session. The defaults match the
+/// official clients, apps registered with their own redirect URI need to set it here.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct RefreshParams {
+    pub redirect_uri: String,
+    pub response_type: String,
+    pub grant_type: String,
+}
+
+impl Default for RefreshParams {
+    fn default() -> Self {
+        Self {
+            redirect_uri: "https://protonmail.ch/".to_string(),
+            response_type: "token".to_string(),
+            grant_type: "refresh_token".to_string(),
+        }
+    }
+}
+
+/// Scopes granted to an authenticated session by the server.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct SessionScope(Vec<String>);
+
+impl SessionScope {
+    /// Parse the space separated scope string returned by the auth endpoints.
+    pub fn from_scope_str(scope: &str) -> Self {
+        Self(scope.split_whitespace().map(|s| s.to_string()).collect())
+    }
+
+    /// Check whether the session was granted `scope`.
+    pub fn contains(&self, scope: &str) -> bool {
+        self.0.iter().any(|s| s == scope)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|s| s.as_str())
+    }
+
+    /// Check whether this scope lacks any of the base scopes granted in `previous`. Scopes
+    /// which the server only grants temporarily, such as the ones obtained by unlocking the
+    /// session, are expected to expire and are not considered.
+    pub fn is_downgrade_of(&self, previous: &SessionScope) -> bool {
+        previous
+            .iter()
+            .filter(|s| !Self::TRANSIENT_SCOPES.contains(s))
+            .any(|s| !self.contains(s))
+    }
+
+    /// Scopes granted for a limited time after unlocking the session.
+    const TRANSIENT_SCOPES: &'static [&'static str] = &["password", "locked"];
+}
+
+impl From<Vec<String>> for SessionScope {
+    fn from(value: Vec<String>) -> Self {
+        Self(value)
+    }
+}
+
+impl Display for SessionScope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.join(" ").fmt(f)
+    }
+}
+
 /// Represents an API User ID.
 #[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 pub struct UserId(pub(crate) String);
 
 impl AsRef<str> for UserId {
@@ -52,6 +141,7 @@ impl Display for UserId {
 
 /// Represent an user's API key ID.
 #[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 pub struct KeyId(pub(crate) String);
 
 impl Display for KeyId {
@@ -61,7 +151,8 @@ impl Display for KeyId {
 }
 
 /// Represents an API user
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
 pub struct User {
     #[serde(rename = "ID")]
@@ -74,10 +165,18 @@ pub struct User {
     pub max_upload: i64,
     pub credit: i64,
     pub currency: String,
+    #[serde(default)]
     pub keys: Vec<Key>,
+    /// Bitmask of the services the user is subscribed to.
+    #[serde(default)]
+    pub subscribed: i64,
+    /// Bitmask of the services available to the user.
+    #[serde(default)]
+    pub services: i64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
 pub struct Key {
     #[serde(rename = "ID")]
@@ -86,13 +185,27 @@ pub struct Key {
     pub token: Option<String>,
     pub signature: Option<String>,
     #[serde(deserialize_with = "bool_from_integer")]
+    #[cfg_attr(feature = "serde-serialize", serde(serialize_with = "bool_to_integer"))]
     pub primary: bool,
     #[serde(deserialize_with = "bool_from_integer")]
+    #[cfg_attr(feature = "serde-serialize", serde(serialize_with = "bool_to_integer"))]
     pub active: bool,
     pub flags: Option<KeyState>,
 }
 
+/// Salt used to derive the passphrase of the user key `id` from the user's password.
+#[derive(Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct KeySalt {
+    #[serde(rename = "ID")]
+    pub id: KeyId,
+    /// Base64 encoded salt, not set for keys which do not use one.
+    pub key_salt: Option<String>,
+}
+
 #[derive(Deserialize_repr, Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde-serialize", derive(serde_repr::Serialize_repr))]
 #[repr(u8)]
 pub enum KeyState {
     None = 0,
@@ -111,3 +224,114 @@ where
         Ok(true)
     }
 }
+
+/// Serialize bool as integer
+#[cfg(feature = "serde-serialize")]
+fn bool_to_integer<S>(value: &bool, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_u8(u8::from(*value))
+}
+
+/// WebAuthn options of the FIDO2 keys which can complete a login, to be passed to the
+/// authenticator. See [`TotpSession::fido2_options`](crate::TotpSession::fido2_options).
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Fido2AuthenticationOptions {
+    pub challenge: Vec<u8>,
+    pub rp_id: Option<String>,
+    #[serde(default)]
+    pub allow_credentials: Vec<Fido2Credential>,
+    /// Time in milliseconds the user has to complete the authentication.
+    pub timeout: Option<u64>,
+    pub user_verification: Option<String>,
+}
+
+/// Credential which may be used for the FIDO2 authentication.
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct Fido2Credential {
+    pub id: Vec<u8>,
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    #[serde(default)]
+    pub transports: Vec<String>,
+}
+
+impl TryFrom<&serde_json::Value> for Fido2AuthenticationOptions {
+    type Error = serde_json::Error;
+
+    fn try_from(value: &serde_json::Value) -> Result<Self, Self::Error> {
+        // The server wraps the options in `publicKey`, as expected by `navigator.credentials.get`.
+        let value = value.get("publicKey").unwrap_or(value);
+        Self::deserialize(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_scope_downgrade() {
+        let full = SessionScope::from_scope_str("full self mail");
+        let reduced = SessionScope::from_scope_str("self mail");
+
+        assert!(reduced.is_downgrade_of(&full));
+        assert!(!full.is_downgrade_of(&reduced));
+        assert!(!full.is_downgrade_of(&full));
+    }
+
+    #[test]
+    fn session_scope_transient_scopes_are_not_a_downgrade() {
+        let unlocked = SessionScope::from_scope_str("full self mail password locked");
+        let full = SessionScope::from_scope_str("full self mail");
+        let reduced = SessionScope::from_scope_str("self mail");
+
+        assert!(!full.is_downgrade_of(&unlocked));
+        assert!(reduced.is_downgrade_of(&unlocked));
+    }
+
+    #[test]
+    fn user_space_exceeds_i32() {
+        let json = r#"{
+            "ID": "user",
+            "Name": "name",
+            "DisplayName": "Display Name",
+            "Email": "user@proton.me",
+            "UsedSpace": 3221225472,
+            "MaxSpace": 1099511627776,
+            "MaxUpload": 26214400,
+            "Credit": 0,
+            "Currency": "EUR"
+        }"#;
+
+        let user: User = serde_json::from_str(json).unwrap();
+        assert_eq!(user.used_space, 3_221_225_472);
+        assert_eq!(user.max_space, 1_099_511_627_776);
+    }
+
+    #[test]
+    fn fido2_authentication_options() {
+        let value = serde_json::json!({
+            "publicKey": {
+                "timeout": 60000,
+                "challenge": [1, 2, 3],
+                "userVerification": "discouraged",
+                "rpId": "proton.me",
+                "allowCredentials": [{"id": [4, 5], "type": "public-key"}]
+            }
+        });
+
+        let options = Fido2AuthenticationOptions::try_from(&value).unwrap();
+        assert_eq!(options.challenge, vec![1, 2, 3]);
+        assert_eq!(options.rp_id.as_deref(), Some("proton.me"));
+        assert_eq!(options.timeout, Some(60000));
+        assert_eq!(options.user_verification.as_deref(), Some("discouraged"));
+        assert_eq!(options.allow_credentials.len(), 1);
+        assert_eq!(options.allow_credentials[0].id, vec![4, 5]);
+        assert_eq!(options.allow_credentials[0].credential_type, "public-key");
+
+        assert!(Fido2AuthenticationOptions::try_from(&serde_json::Value::Null).is_err());
+    }
+}
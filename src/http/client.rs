@@ -1,20 +1,60 @@
-use crate::http::{Proxy, RequestData, Result, DEFAULT_APP_VERSION, DEFAULT_HOST_URL};
-use std::future::Future;
 #[cfg(not(feature = "async-traits"))]
-use std::pin::Pin;
+use crate::http::BoxFuture;
+use crate::http::{
+    BytesResponse, CookieJar, MaybeSend, Metrics, MetricsHook, Proxy, RequestData, Result,
+    DEFAULT_APP_VERSION, DEFAULT_HOST_URL, DEFAULT_MAX_BODY_SIZE,
+};
+use bytes::Bytes;
+use std::collections::HashMap;
+#[cfg(feature = "async-traits")]
+use std::future::Future;
+use std::sync::Arc;
 use std::time::Duration;
 
+/// Minimum TLS version accepted by the client.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum TlsVersion {
+    #[default]
+    Tls1_2,
+    Tls1_3,
+}
+
+/// How the client follows redirects.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RedirectPolicy {
+    /// Don't follow redirects, requests which are redirected fail with
+    /// [`Error::Redirect`](crate::http::Error::Redirect).
+    None,
+    /// Follow at most this many redirects.
+    Limited(usize),
+}
+
 /// Builder for an http client
 #[derive(Debug, Clone)]
 pub struct ClientBuilder {
     pub(super) app_version: String,
     pub(super) base_url: String,
+    pub(super) alternative_urls: Vec<String>,
     pub(super) request_timeout: Option<Duration>,
     pub(super) connect_timeout: Option<Duration>,
+    pub(super) read_timeout: Option<Duration>,
+    pub(super) write_timeout: Option<Duration>,
     pub(super) user_agent: String,
     pub(super) proxy_url: Option<Proxy>,
+    pub(super) no_proxy: Vec<String>,
     pub(super) debug: bool,
     pub(super) allow_http: bool,
+    pub(super) http2_prior_knowledge: bool,
+    pub(super) tcp_keepalive: Option<Duration>,
+    pub(super) pool_idle_timeout: Option<Duration>,
+    pub(super) cookies: Option<CookieJar>,
+    pub(super) cookies_enabled: bool,
+    pub(super) request_ids: bool,
+    pub(super) metrics: Option<MetricsHook>,
+    pub(super) max_body_size: usize,
+    pub(super) default_headers: HashMap<String, String>,
+    pub(super) min_tls_version: TlsVersion,
+    pub(super) redirect_policy: Option<RedirectPolicy>,
 }
 
 impl Default for ClientBuilder {
@@ -29,11 +69,26 @@ impl ClientBuilder {
             app_version: DEFAULT_APP_VERSION.to_string(),
             user_agent: "NoClient/0.1.0".to_string(),
             base_url: DEFAULT_HOST_URL.to_string(),
+            alternative_urls: Vec::new(),
             request_timeout: None,
             connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
             proxy_url: None,
+            no_proxy: Vec::new(),
             debug: false,
             allow_http: false,
+            http2_prior_knowledge: false,
+            tcp_keepalive: None,
+            pool_idle_timeout: None,
+            cookies: None,
+            cookies_enabled: true,
+            request_ids: false,
+            metrics: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            default_headers: HashMap::new(),
+            min_tls_version: TlsVersion::default(),
+            redirect_policy: None,
         }
     }
 
@@ -56,6 +111,24 @@ impl ClientBuilder {
         self
     }
 
+    /// Add a header to be submitted with every request. Headers set by individual requests take
+    /// precedence over the default ones.
+    pub fn default_header(mut self, key: &str, value: &str) -> Self {
+        self.default_headers
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Alternative base urls to fall back to, in order, when requests against the base url fail
+    /// with [`Error::Connection`](crate::http::Error::Connection), e.g. in networks where the
+    /// proton servers are blocked. Each alternative is used as is, so its host name is also used
+    /// for the `Host` header and TLS SNI. Once an alternative succeeds, it is used for all
+    /// subsequent requests of the client and its clones.
+    pub fn alternative_routing(mut self, urls: Vec<String>) -> Self {
+        self.alternative_urls = urls;
+        self
+    }
+
     /// Set the full request timeout. By default there is no timeout.
     pub fn request_timeout(mut self, duration: Duration) -> Self {
         self.request_timeout = Some(duration);
@@ -68,29 +141,143 @@ impl ClientBuilder {
         self
     }
 
+    /// Set the timeout for individual socket reads. By default there is no timeout.
+    /// Note: Ignored by the reqwest backend.
+    pub fn read_timeout(mut self, duration: Duration) -> Self {
+        self.read_timeout = Some(duration);
+        self
+    }
+
+    /// Set the timeout for individual socket writes. By default there is no timeout.
+    /// Note: Ignored by the reqwest backend.
+    pub fn write_timeout(mut self, duration: Duration) -> Self {
+        self.write_timeout = Some(duration);
+        self
+    }
+
     /// Specify proxy URL for the builder.
     pub fn with_proxy(mut self, proxy: Proxy) -> Self {
         self.proxy_url = Some(proxy);
         self
     }
 
-    /// Allow http request
+    /// Hosts for which the proxy should not be used, e.g.: `localhost` or `.example.com`.
+    pub fn no_proxy(mut self, hosts: Vec<String>) -> Self {
+        self.no_proxy = hosts;
+        self
+    }
+
+    /// Allow plain http requests, e.g. against a local test server. By default both the ureq and
+    /// reqwest backends only allow https.
     pub fn allow_http(mut self) -> Self {
         self.allow_http = true;
         self
     }
 
+    /// Only use HTTP/2 and multiplex requests over a single connection.
+    /// Note: Ignored by the ureq backend, which only supports HTTP/1.1.
+    pub fn http2_prior_knowledge(mut self) -> Self {
+        self.http2_prior_knowledge = true;
+        self
+    }
+
+    /// Set the TCP keepalive interval for the connections. By default keepalive is disabled.
+    /// Note: Ignored by the ureq backend.
+    pub fn tcp_keepalive(mut self, duration: Duration) -> Self {
+        self.tcp_keepalive = Some(duration);
+        self
+    }
+
+    /// Close pooled connections which have been idle for longer than `duration`, instead of
+    /// reusing them after the server may already have reset them.
+    /// Note: Ignored by the ureq backend, which never keeps idle connections around.
+    pub fn pool_idle_timeout(mut self, duration: Duration) -> Self {
+        self.pool_idle_timeout = Some(duration);
+        self
+    }
+
+    /// Set the minimum TLS version for https connections. Defaults to TLS 1.2.
+    pub fn min_tls_version(mut self, version: TlsVersion) -> Self {
+        self.min_tls_version = version;
+        self
+    }
+
+    /// Set how redirects are followed, e.g. [`RedirectPolicy::None`] to make sure requests
+    /// carrying credentials are never sent elsewhere. By default the policy of the underlying
+    /// http client applies.
+    pub fn redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect_policy = Some(policy);
+        self
+    }
+
+    /// Initialize the client's cookie store with previously exported cookies.
+    pub fn with_cookies(mut self, cookies: CookieJar) -> Self {
+        self.cookies = Some(cookies);
+        self
+    }
+
+    /// Neither store the cookies set by the server nor send any with requests, e.g. for clients
+    /// shared by several users. Cookies passed to [`ClientBuilder::with_cookies`] are ignored.
+    /// By default cookies are enabled.
+    pub fn disable_cookies(mut self) -> Self {
+        self.cookies_enabled = false;
+        self
+    }
+
+    /// Attach a unique `X-Request-Id` header to every request. The id is recorded in
+    /// [`APIError::request_id`](crate::requests::APIError::request_id) unless the server replied
+    /// with its own.
+    pub fn generate_request_ids(mut self) -> Self {
+        self.request_ids = true;
+        self
+    }
+
+    /// Report the start, end and duration of every request to `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = Some(MetricsHook(metrics));
+        self
+    }
+
+    /// Set the maximum size of a response body in bytes, larger responses fail with
+    /// [`Error::Request`](crate::http::Error::Request). Defaults to 10MB.
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
     /// Enable request debugging.
     pub fn debug(mut self) -> Self {
         self.debug = true;
         self
     }
 
+    /// Check for options which would produce requests rejected by the server.
+    #[allow(unused)] // Only used by http implementations.
+    pub(super) fn validate(&self) -> std::result::Result<(), anyhow::Error> {
+        if self.app_version.trim().is_empty() {
+            return Err(anyhow::anyhow!("app_version must not be empty"));
+        }
+
+        if self.user_agent.trim().is_empty() {
+            return Err(anyhow::anyhow!("user_agent must not be empty"));
+        }
+
+        Ok(())
+    }
+
     pub fn build<T: TryFrom<ClientBuilder, Error = anyhow::Error> + Clone>(
         self,
     ) -> std::result::Result<T, anyhow::Error> {
         T::try_from(self)
     }
+
+    /// Build a client without consuming the builder, so that the same configuration can be used
+    /// to create several clients, e.g. both a sync and an async one.
+    pub fn build_ref<T: TryFrom<ClientBuilder, Error = anyhow::Error> + Clone>(
+        &self,
+    ) -> std::result::Result<T, anyhow::Error> {
+        self.clone().build()
+    }
 }
 pub trait ClientRequest: Sized + Send {
     fn header(self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self;
@@ -108,6 +295,17 @@ pub trait ClientRequestBuilder: Clone {
 /// HTTP Client abstraction Sync.
 pub trait ClientSync: ClientRequestBuilder + TryFrom<ClientBuilder, Error = anyhow::Error> {
     fn execute<R: FromResponse>(&self, request: Self::Request) -> Result<R::Output>;
+
+    /// Send an already built request, e.g. one which was queued for later, and return the raw
+    /// response body.
+    fn execute_raw(&self, data: RequestData) -> Result<Bytes> {
+        self.execute::<BytesResponse>(self.new_request(&data))
+    }
+
+    /// Block the current thread for `duration`.
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
 }
 
 /// HTTP Client abstraction Async.
@@ -118,25 +316,78 @@ pub trait ClientAsync:
     fn execute_async<R: FromResponse>(
         &self,
         request: Self::Request,
-    ) -> Pin<Box<dyn Future<Output = Result<R::Output>> + '_>>;
+    ) -> BoxFuture<'_, Result<R::Output>>;
 
     #[cfg(feature = "async-traits")]
     fn execute_async<R: FromResponse>(
         &self,
         request: Self::Request,
     ) -> impl Future<Output = Result<R::Output>>;
+
+    /// Async version of [`ClientSync::execute_raw`].
+    #[cfg(not(feature = "async-traits"))]
+    fn execute_raw_async(&self, data: RequestData) -> BoxFuture<'_, Result<Bytes>> {
+        self.execute_async::<BytesResponse>(self.new_request(&data))
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn execute_raw_async(&self, data: RequestData) -> impl Future<Output = Result<Bytes>> {
+        self.execute_async::<BytesResponse>(self.new_request(&data))
+    }
+
+    /// Run a CPU bound operation without stalling the async executor, e.g. on a thread where
+    /// blocking is acceptable.
+    #[cfg(not(feature = "async-traits"))]
+    fn execute_blocking<F, R>(&self, f: F) -> BoxFuture<'_, Result<R>>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static;
+
+    #[cfg(feature = "async-traits")]
+    fn execute_blocking<F, R>(&self, f: F) -> impl Future<Output = Result<R>>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static;
+
+    /// Wait for `duration` using the timer of the async runtime.
+    #[cfg(not(feature = "async-traits"))]
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()>;
+
+    #[cfg(feature = "async-traits")]
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()>;
 }
 
 pub trait ResponseBodySync {
-    type Body: AsRef<[u8]>;
+    type Body: AsRef<[u8]> + Into<Bytes> + 'static;
     fn get_body(self) -> Result<Self::Body>;
+
+    /// Value of the response header `name`, if present and valid UTF-8. Bodies without headers
+    /// don't need to implement this.
+    fn header(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    /// Read the body as a stream, so that it does not need to be buffered in full. By default
+    /// this reads from the buffered body.
+    fn get_body_reader(self) -> Result<Box<dyn std::io::Read>>
+    where
+        Self: Sized,
+    {
+        Ok(Box::new(std::io::Cursor::new(self.get_body()?)))
+    }
 }
 
 pub trait ResponseBodyAsync {
-    type Body: AsRef<[u8]>;
+    type Body: AsRef<[u8]> + Into<Bytes> + MaybeSend;
+
+    /// Value of the response header `name`, if present and valid UTF-8. Bodies without headers
+    /// don't need to implement this.
+    fn header(&self, _name: &str) -> Option<String> {
+        None
+    }
 
     #[cfg(not(feature = "async-traits"))]
-    fn get_body_async(self) -> Pin<Box<dyn Future<Output = Result<Self::Body>>>>;
+    fn get_body_async(self) -> BoxFuture<'static, Result<Self::Body>>;
 
     #[cfg(feature = "async-traits")]
     fn get_body_async(self) -> impl Future<Output = Result<Self::Body>>;
@@ -147,12 +398,28 @@ pub trait FromResponse {
     fn from_response_sync<T: ResponseBodySync>(response: T) -> Result<Self::Output>;
 
     #[cfg(not(feature = "async-traits"))]
-    fn from_response_async<T: ResponseBodyAsync + 'static>(
+    fn from_response_async<T: ResponseBodyAsync + MaybeSend + 'static>(
         response: T,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output>>>>;
+    ) -> BoxFuture<'static, Result<Self::Output>>;
 
     #[cfg(feature = "async-traits")]
-    fn from_response_async<T: ResponseBodyAsync + 'static>(
+    fn from_response_async<T: ResponseBodyAsync + MaybeSend + 'static>(
         response: T,
     ) -> impl Future<Output = Result<Self::Output>>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_builder_rejects_empty_headers() {
+        assert!(ClientBuilder::new().validate().is_ok());
+
+        let err = ClientBuilder::new().app_version("").validate().unwrap_err();
+        assert_eq!(err.to_string(), "app_version must not be empty");
+
+        let err = ClientBuilder::new().user_agent(" ").validate().unwrap_err();
+        assert_eq!(err.to_string(), "user_agent must not be empty");
+    }
+}
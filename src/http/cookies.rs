@@ -0,0 +1,51 @@
+/// Backend agnostic snapshot of the cookies of a client.
+///
+/// Cookies are stored in JSON lines format so they can be persisted alongside the session refresh
+/// data and restored with [`crate::http::ClientBuilder::with_cookies`].
+#[derive(Clone, Default, Eq, PartialEq)]
+pub struct CookieJar(String);
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restore a jar from a previously exported JSON value.
+    pub fn from_json(json: impl Into<String>) -> Self {
+        Self(json.into())
+    }
+
+    /// JSON representation of the jar, suitable for persistence.
+    pub fn as_json(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Cookie values may contain session information, do not print them.
+        write!(f, "CookieJar(..)")
+    }
+}
+
+#[cfg(any(feature = "http-ureq", feature = "http-reqwest"))]
+impl CookieJar {
+    pub(crate) fn to_store(&self) -> anyhow::Result<cookie_store::CookieStore> {
+        cookie_store::CookieStore::load_json(self.0.as_bytes())
+            .map_err(|e| anyhow::anyhow!("Failed to load cookies: {e}"))
+    }
+
+    pub(crate) fn from_store(store: &cookie_store::CookieStore) -> anyhow::Result<Self> {
+        let mut json = Vec::new();
+        // Session cookies need to be included as well, as these are the ones that matter to the
+        // API.
+        store
+            .save_incl_expired_and_nonpersistent_json(&mut json)
+            .map_err(|e| anyhow::anyhow!("Failed to save cookies: {e}"))?;
+        Ok(Self(String::from_utf8(json)?))
+    }
+}
@@ -0,0 +1,76 @@
+use crate::http::{Error, Method, RequestData, Result};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Hooks invoked by the http clients around every request, see [`ClientBuilder::with_metrics`].
+///
+/// The `url` is the request path relative to the client's base url, e.g.: `core/v4/users`.
+///
+/// [`ClientBuilder::with_metrics`]: crate::http::ClientBuilder::with_metrics
+pub trait Metrics: Send + Sync {
+    fn on_request_start(&self, _method: Method, _url: &str) {}
+
+    fn on_request_end(
+        &self,
+        _method: Method,
+        _url: &str,
+        _duration: Duration,
+        _result: std::result::Result<(), &Error>,
+    ) {
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MetricsHook(pub(crate) Arc<dyn Metrics>);
+
+impl Debug for MetricsHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MetricsHook")
+    }
+}
+
+/// Request details captured for the metrics hooks. Only created when metrics are enabled.
+#[allow(unused)] // Only used by http implementations.
+pub(crate) struct RequestMetrics {
+    metrics: Arc<dyn Metrics>,
+    method: Method,
+    url: String,
+}
+
+#[allow(unused)] // Only used by http implementations.
+impl RequestMetrics {
+    pub(crate) fn new(hook: &Option<MetricsHook>, data: &RequestData) -> Option<Self> {
+        hook.as_ref().map(|h| Self {
+            metrics: h.0.clone(),
+            method: data.method,
+            url: data.url.clone(),
+        })
+    }
+
+    pub(crate) fn start(self) -> RequestTimer {
+        self.metrics.on_request_start(self.method, &self.url);
+        RequestTimer {
+            request: self,
+            start: Instant::now(),
+        }
+    }
+}
+
+#[allow(unused)] // Only used by http implementations.
+pub(crate) struct RequestTimer {
+    request: RequestMetrics,
+    start: Instant,
+}
+
+#[allow(unused)] // Only used by http implementations.
+impl RequestTimer {
+    pub(crate) fn end<T>(self, result: &Result<T>) {
+        self.request.metrics.on_request_end(
+            self.request.method,
+            &self.request.url,
+            self.start.elapsed(),
+            result.as_ref().map(|_| ()),
+        );
+    }
+}
@@ -2,6 +2,10 @@
 
 use anyhow;
 use std::fmt::Debug;
+#[cfg(not(feature = "async-traits"))]
+use std::future::Future;
+#[cfg(not(feature = "async-traits"))]
+use std::pin::Pin;
 use thiserror::Error;
 
 #[cfg(feature = "http-ureq")]
@@ -11,12 +15,17 @@ pub mod ureq_client;
 pub mod reqwest_client;
 
 mod client;
+mod cookies;
+mod metrics;
 mod proxy;
 mod request;
 mod response;
+mod routing;
 mod sequence;
 
 pub use client::*;
+pub use cookies::*;
+pub use metrics::*;
 pub use proxy::*;
 pub use request::*;
 pub use response::*;
@@ -24,12 +33,41 @@ pub use sequence::*;
 
 pub(crate) const DEFAULT_HOST_URL: &str = "https://mail.proton.me/api";
 pub(crate) const DEFAULT_APP_VERSION: &str = "proton-api-rs";
+pub(crate) const DEFAULT_MAX_BODY_SIZE: usize = 10_000_000;
 #[allow(unused)] // it is used by the http implementations
 pub(crate) const X_PM_APP_VERSION_HEADER: &str = "X-Pm-Appversion";
 pub(crate) const X_PM_UID_HEADER: &str = "X-Pm-Uid";
+#[allow(unused)] // Only used by http implementations.
+pub(crate) const X_REQUEST_ID_HEADER: &str = "X-Request-Id";
+#[allow(unused)] // Only used by http implementations.
+pub(crate) const RETRY_AFTER_HEADER: &str = "Retry-After";
 pub(crate) const X_PM_HUMAN_VERIFICATION_TOKEN: &str = "X-Pm-Human-Verification-Token";
 pub(crate) const X_PM_HUMAN_VERIFICATION_TOKEN_TYPE: &str = "X-Pm-Human-Verification-Token-Type";
 
+/// Marker trait which requires `Send` when the `async-send` feature is enabled, so that the
+/// futures produced by the async API can be spawned on multi-threaded executors.
+#[cfg(feature = "async-send")]
+pub trait MaybeSend: Send {}
+
+#[cfg(feature = "async-send")]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// Marker trait which requires `Send` when the `async-send` feature is enabled, so that the
+/// futures produced by the async API can be spawned on multi-threaded executors.
+#[cfg(not(feature = "async-send"))]
+pub trait MaybeSend {}
+
+#[cfg(not(feature = "async-send"))]
+impl<T: ?Sized> MaybeSend for T {}
+
+/// Boxed future returned by the async API.
+#[cfg(all(not(feature = "async-traits"), not(feature = "async-send")))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Boxed future returned by the async API.
+#[cfg(all(not(feature = "async-traits"), feature = "async-send"))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
 /// HTTP method.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Method {
@@ -38,13 +76,33 @@ pub enum Method {
     Put,
     Post,
     Patch,
+    /// Same as [`Method::Get`], but the server only replies with the headers. Use
+    /// [`HeadResponse`] to read them.
+    Head,
 }
 
 /// Errors that may occur during an HTTP request, mostly related to network.
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("API Error: {0}")]
-    API(#[from] crate::requests::APIError),
+    API(crate::requests::APIError),
+    #[error("App version is no longer supported, minimum version: {min_version:?}")]
+    AppVersionTooOld { min_version: Option<String> },
+    #[error("Session scope was downgraded from '{from}' to '{to}'")]
+    ScopeDowngraded {
+        from: crate::domain::SessionScope,
+        to: crate::domain::SessionScope,
+    },
+    #[error("Session lacks the scope required by the request: {required:?}")]
+    InsufficientScope {
+        required: Option<String>,
+        #[source]
+        source: Box<crate::requests::APIError>,
+    },
+    /// The refresh token of the session is no longer valid. Retrying won't help, the user has to
+    /// login again.
+    #[error("Session expired, a new login is required: {0}")]
+    SessionExpired(#[source] crate::requests::APIError),
     #[error("A redirect error occurred at '{0}: {1}")]
     Redirect(String, #[source] anyhow::Error),
     #[error("Connection timed out")]
@@ -59,10 +117,198 @@ pub enum Error {
     Other(#[source] anyhow::Error),
 }
 
+impl Error {
+    /// Create a copy of this error. API errors are copied as is, while the other variants only
+    /// retain the formatted message of the underlying error and its causes.
+    pub fn clone_shallow(&self) -> Self {
+        fn shallow(e: &anyhow::Error) -> anyhow::Error {
+            anyhow::anyhow!("{e:#}")
+        }
+
+        match self {
+            Self::API(e) => Self::API(e.clone()),
+            Self::AppVersionTooOld { min_version } => Self::AppVersionTooOld {
+                min_version: min_version.clone(),
+            },
+            Self::ScopeDowngraded { from, to } => Self::ScopeDowngraded {
+                from: from.clone(),
+                to: to.clone(),
+            },
+            Self::InsufficientScope { required, source } => Self::InsufficientScope {
+                required: required.clone(),
+                source: source.clone(),
+            },
+            Self::SessionExpired(e) => Self::SessionExpired(e.clone()),
+            Self::Redirect(url, e) => Self::Redirect(url.clone(), shallow(e)),
+            Self::Timeout(e) => Self::Timeout(shallow(e)),
+            Self::Connection(e) => Self::Connection(shallow(e)),
+            Self::Request(e) => Self::Request(shallow(e)),
+            Self::EncodeOrDecode(e) => Self::EncodeOrDecode(shallow(e)),
+            Self::Other(e) => Self::Other(shallow(e)),
+        }
+    }
+}
+
+impl Error {
+    /// Record `request_id` on API errors which did not receive one from the server.
+    #[allow(unused)] // Only used by http implementations.
+    pub(crate) fn or_request_id(self, request_id: Option<String>) -> Self {
+        match self {
+            Self::API(mut e) => {
+                if e.request_id.is_none() {
+                    e.request_id = request_id;
+                }
+                Self::API(e)
+            }
+            Self::SessionExpired(mut e) => {
+                if e.request_id.is_none() {
+                    e.request_id = request_id;
+                }
+                Self::SessionExpired(e)
+            }
+            Self::InsufficientScope {
+                required,
+                mut source,
+            } => {
+                if source.request_id.is_none() {
+                    source.request_id = request_id;
+                }
+                Self::InsufficientScope { required, source }
+            }
+            e => e,
+        }
+    }
+}
+
+impl From<crate::requests::APIError> for Error {
+    fn from(value: crate::requests::APIError) -> Self {
+        if value.is_app_version_too_old() {
+            return Self::AppVersionTooOld {
+                min_version: value.min_app_version(),
+            };
+        }
+
+        if value.is_insufficient_scope() {
+            return Self::InsufficientScope {
+                required: value.required_scope(),
+                source: Box::new(value),
+            };
+        }
+
+        if value.is_invalid_refresh_token() {
+            return Self::SessionExpired(value);
+        }
+
+        Self::API(value)
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(value: serde_json::Error) -> Self {
         Self::EncodeOrDecode(value.into())
     }
 }
 
+impl From<Error> for std::io::Error {
+    fn from(value: Error) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match &value {
+            Error::Timeout(_) => ErrorKind::TimedOut,
+            Error::Connection(_) => ErrorKind::ConnectionReset,
+            Error::API(e) if e.http_code == 404 => ErrorKind::NotFound,
+            Error::API(e) if e.http_code == 401 || e.http_code == 403 => {
+                ErrorKind::PermissionDenied
+            }
+            Error::InsufficientScope { .. } | Error::SessionExpired(_) => {
+                ErrorKind::PermissionDenied
+            }
+            _ => ErrorKind::Other,
+        };
+
+        std::io::Error::new(kind, value)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_clone_shallow_keeps_message() {
+        let err = Error::Connection(anyhow::anyhow!("refused").context("failed to connect"));
+        let cloned = err.clone_shallow();
+
+        assert!(matches!(cloned, Error::Connection(_)));
+        assert_eq!(
+            cloned.to_string(),
+            "Connection error: failed to connect: refused"
+        );
+    }
+
+    #[test]
+    fn error_into_io_error() {
+        use crate::requests::APIError;
+        use std::io::ErrorKind;
+
+        let kind = |e: Error| std::io::Error::from(e).kind();
+        assert_eq!(
+            kind(Error::Timeout(anyhow::anyhow!("t"))),
+            ErrorKind::TimedOut
+        );
+        assert_eq!(
+            kind(Error::Connection(anyhow::anyhow!("c"))),
+            ErrorKind::ConnectionReset
+        );
+        assert_eq!(kind(Error::API(APIError::new(404))), ErrorKind::NotFound);
+        assert_eq!(
+            kind(Error::API(APIError::new(401))),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            kind(Error::API(APIError::new(403))),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(kind(Error::API(APIError::new(500))), ErrorKind::Other);
+        assert_eq!(kind(Error::Other(anyhow::anyhow!("o"))), ErrorKind::Other);
+
+        let err = std::io::Error::from(Error::API(APIError::new(404)));
+        let inner = err.into_inner().unwrap().downcast::<Error>().unwrap();
+        assert!(matches!(*inner, Error::API(_)));
+    }
+
+    #[test]
+    fn error_from_insufficient_scope() {
+        use crate::requests::APIError;
+
+        let body =
+            br#"{"Code": 9101, "Error": "Insufficient scope", "Details": {"Scope": "locked"}}"#;
+        let err = Error::from(APIError::with_status_and_body(403, body));
+        assert!(matches!(
+            err,
+            Error::InsufficientScope { required: Some(ref s), ref source }
+                if s == "locked" && source.http_code == 403
+        ));
+
+        let err = Error::from(APIError::with_status_and_body(403, br#"{"Code": 9101}"#));
+        assert!(matches!(
+            err,
+            Error::InsufficientScope { required: None, .. }
+        ));
+    }
+
+    #[test]
+    fn error_from_invalid_refresh_token() {
+        use crate::requests::APIError;
+
+        let body = br#"{"Code": 10013, "Error": "Invalid refresh token"}"#;
+        let err = Error::from(APIError::with_status_and_body(422, body));
+        assert!(matches!(err, Error::SessionExpired(ref e) if e.http_code == 422));
+        assert!(matches!(
+            err.or_request_id(Some("id".to_string())),
+            Error::SessionExpired(APIError { request_id: Some(ref id), .. }) if id == "id"
+        ));
+    }
+}
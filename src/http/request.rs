@@ -1,21 +1,32 @@
-use crate::http::{ClientAsync, ClientRequestBuilder, ClientSync, Error, FromResponse, Method};
+use crate::domain::UserUid;
+#[cfg(not(feature = "async-traits"))]
+use crate::http::{BoxFuture, MaybeSend};
+use crate::http::{
+    BytesResponse, ClientAsync, ClientRequestBuilder, ClientSync, Error, FromResponse, Method,
+    ResponseBodySync, Sequence, X_PM_UID_HEADER,
+};
 use bytes::Bytes;
 use serde::Serialize;
-use std::collections::HashMap;
+#[cfg(feature = "async-traits")]
 use std::future::Future;
 use std::marker::PhantomData;
-#[cfg(not(feature = "async-traits"))]
-use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// HTTP Request representation.
+///
+/// Cloning is cheap as the body is shared between the clones, which makes it possible to keep a
+/// request around and replay the exact same bytes, e.g. to retry it after a session refresh.
 #[derive(Debug, Clone)]
 pub struct RequestData {
-    #[allow(unused)] // Only used by http implementations.
     pub(super) method: Method,
-    #[allow(unused)] // Only used by http implementations.
     pub(super) url: String,
-    pub(super) headers: HashMap<String, String>,
+    /// Kept in the order they were set, with the casing of their name preserved.
+    pub(super) headers: Vec<(String, String)>,
     pub(super) body: Option<Bytes>,
+    pub(super) timeout: Option<Duration>,
+    pub(super) cancel: Option<Arc<AtomicBool>>,
 }
 
 impl RequestData {
@@ -23,13 +34,64 @@ impl RequestData {
         Self {
             method,
             url: url.into(),
-            headers: HashMap::new(),
+            headers: Vec::new(),
             body: None,
+            timeout: None,
+            cancel: None,
         }
     }
 
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Url of the request relative to the client's base url.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    #[cfg(test)]
+    pub(crate) fn body(&self) -> Option<&[u8]> {
+        self.body.as_deref()
+    }
+
+    /// Set the header `key`, replacing the value of a header with the same name in any casing.
+    /// The ureq client sends headers with the casing of `key`, while the reqwest client can only
+    /// send them in title case, e.g. `X-Pm-Uid`, or in lower case over HTTP/2.
     pub fn header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
-        self.headers.insert(key.into(), value.into());
+        let (key, value) = (key.into(), value.into());
+        match self
+            .headers
+            .iter_mut()
+            .find(|(k, _)| k.eq_ignore_ascii_case(&key))
+        {
+            Some(header) => *header = (key, value),
+            None => self.headers.push((key, value)),
+        }
+        self
+    }
+
+    /// Value of the header `key`, whatever its casing.
+    #[cfg(any(test, feature = "http-ureq"))]
+    pub(super) fn header_value(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Override the client's request timeout for this request only.
+    pub fn timeout(mut self, duration: Duration) -> Self {
+        self.timeout = Some(duration);
+        self
+    }
+
+    /// Abort the request with [`Error::Connection`] once `flag` is set. The flag is checked before
+    /// the request is sent and again before the response body is read, a request which is already
+    /// waiting on the server is not interrupted, use [`RequestData::timeout`] to bound it.
+    /// Note: Ignored by the reqwest backend, where dropping the future cancels the request.
+    pub fn cancel_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
         self
     }
 
@@ -37,6 +99,11 @@ impl RequestData {
         self.header("authorization", format!("Bearer {}", token.as_ref()))
     }
 
+    /// Identify the session the request belongs to, together with [`RequestData::bearer_token`].
+    pub fn pm_uid(self, uid: &UserUid) -> Self {
+        self.header(X_PM_UID_HEADER, uid.as_str())
+    }
+
     pub fn bytes(mut self, bytes: impl Into<Bytes>) -> Self {
         self.body = Some(bytes.into());
         self
@@ -61,9 +128,54 @@ pub trait RequestDesc {
     fn to_request(&self) -> OwnedRequest<Self::Response> {
         OwnedRequest(self.build(), PhantomData)
     }
+
+    /// Wrap this request so that extra modifications, such as headers, can be stacked on top.
+    fn compose(self) -> ComposeRequest<Self>
+    where
+        Self: Sized,
+    {
+        ComposeRequest::new(self)
+    }
+}
+
+type RequestDataFn = Box<dyn Fn(RequestData) -> RequestData + Send + Sync>;
+
+/// Wraps a [`RequestDesc`] and applies a list of functions to the built [`RequestData`] in the
+/// order they were added.
+///
+/// When executed through a [`Session`](crate::Session), the session authentication headers are
+/// applied after all the functions have run.
+pub struct ComposeRequest<R: RequestDesc> {
+    inner: R,
+    fns: Vec<RequestDataFn>,
+}
+
+impl<R: RequestDesc> ComposeRequest<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            fns: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, f: impl Fn(RequestData) -> RequestData + Send + Sync + 'static) -> Self {
+        self.fns.push(Box::new(f));
+        self
+    }
+}
+
+impl<R: RequestDesc> RequestDesc for ComposeRequest<R> {
+    type Output = R::Output;
+    type Response = R::Response;
+
+    fn build(&self) -> RequestData {
+        self.fns.iter().fold(self.inner.build(), |data, f| f(data))
+    }
 }
 
-pub struct OwnedRequest<F: FromResponse>(RequestData, PhantomData<F>);
+// The response type is only used as a marker, it does not affect whether the request can be sent
+// between threads.
+pub struct OwnedRequest<F: FromResponse>(RequestData, PhantomData<fn() -> F>);
 
 impl<F: FromResponse> OwnedRequest<F> {
     pub fn new(r: RequestData) -> Self {
@@ -85,9 +197,125 @@ impl<F: FromResponse> Request for OwnedRequest<F> {
     }
 }
 
+type DecodeFn<O> = Box<dyn FnOnce(Bytes) -> Result<O, Error> + Send>;
+
+/// Request with its response type erased, so that requests of different types can be stored and
+/// executed uniformly, e.g. from a `Vec<BoxedRequest>`. By default the raw response body is
+/// returned, requests converted from a [`RequestDesc`] decode the body as the original request
+/// would.
+///
+/// Use [`Session::request_boxed`](crate::Session::request_boxed) to execute it with a session's
+/// authentication.
+pub struct BoxedRequest<O = Bytes> {
+    pub(crate) data: RequestData,
+    pub(crate) decode: DecodeFn<O>,
+}
+
+impl BoxedRequest {
+    pub fn new(data: RequestData) -> Self {
+        Self {
+            data,
+            decode: Box::new(Ok),
+        }
+    }
+}
+
+impl<O: 'static> BoxedRequest<O> {
+    /// Transform the output of the request once decoded.
+    pub fn map<O2>(
+        self,
+        f: impl FnOnce(O) -> Result<O2, Error> + Send + 'static,
+    ) -> BoxedRequest<O2> {
+        let decode = self.decode;
+        BoxedRequest {
+            data: self.data,
+            decode: Box::new(move |body| decode(body).and_then(f)),
+        }
+    }
+}
+
+impl<R: RequestDesc> From<R> for BoxedRequest<R::Output>
+where
+    R::Output: 'static,
+{
+    fn from(value: R) -> Self {
+        let decode: fn(Bytes) -> Result<R::Output, Error> =
+            |body| R::Response::from_response_sync(BufferedBody(body));
+        Self {
+            data: value.build(),
+            decode: Box::new(decode),
+        }
+    }
+}
+
+impl<O> Sequence for BoxedRequest<O> {
+    type Output = O;
+    type Error = Error;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        let body = OwnedRequest::<BytesResponse>::new(self.data).do_sync(client)?;
+        (self.decode)(body)
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> BoxFuture<'a, Result<Self::Output, Self::Error>>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(async move {
+            let body = OwnedRequest::<BytesResponse>::new(self.data)
+                .do_async(client)
+                .await?;
+            (self.decode)(body)
+        })
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl Future<Output = Result<Self::Output, Self::Error>> + 'a
+    where
+        Self: 'a,
+    {
+        async move {
+            let body = OwnedRequest::<BytesResponse>::new(self.data)
+                .do_async(client)
+                .await?;
+            (self.decode)(body)
+        }
+    }
+}
+
+/// Request returning the raw response body, used to execute a [`BoxedRequest`] through other
+/// request wrappers.
+pub(crate) struct RawRequest(pub(crate) RequestData);
+
+impl RequestDesc for RawRequest {
+    type Output = Bytes;
+    type Response = BytesResponse;
+
+    fn build(&self) -> RequestData {
+        self.0.clone()
+    }
+}
+
+/// Response body which was already read in full.
+struct BufferedBody(Bytes);
+
+impl ResponseBodySync for BufferedBody {
+    type Body = Bytes;
+
+    fn get_body(self) -> Result<Self::Body, Error> {
+        Ok(self.0)
+    }
+}
+
 #[cfg(not(feature = "async-traits"))]
-type RequestFuture<'a, F> =
-    Pin<Box<dyn Future<Output = Result<<F as FromResponse>::Output, Error>> + 'a>>;
+type RequestFuture<'a, F> = BoxFuture<'a, Result<<F as FromResponse>::Output, Error>>;
 
 pub trait Request {
     type Response: FromResponse;
@@ -119,3 +347,73 @@ pub trait Request {
         async move { client.execute_async::<Self::Response>(v).await }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compose_request_applies_functions_in_order() {
+        use crate::http::NoResponse;
+
+        struct Ping;
+
+        impl RequestDesc for Ping {
+            type Output = ();
+            type Response = NoResponse;
+
+            fn build(&self) -> RequestData {
+                RequestData::new(Method::Get, "tests/ping")
+            }
+        }
+
+        let data = Ping
+            .compose()
+            .with(|r| r.header("x-trace-id", "1"))
+            .with(|r| r.header("x-trace-id", "2").header("x-other", "3"))
+            .build();
+
+        assert_eq!(data.url, "tests/ping");
+        assert_eq!(data.header_value("x-trace-id"), Some("2"));
+        assert_eq!(data.header_value("x-other"), Some("3"));
+    }
+
+    #[test]
+    fn request_data_pm_uid() {
+        let uid = UserUid::from("uid");
+        let data = RequestData::new(Method::Get, "tests/ping").pm_uid(&uid);
+        assert_eq!(data.header_value(X_PM_UID_HEADER), Some("uid"));
+        assert_eq!(data.headers[0].0, "X-Pm-Uid");
+    }
+
+    #[test]
+    fn request_data_clone_shares_body() {
+        let data = RequestData::new(Method::Post, "tests/ping")
+            .json(serde_json::json!({"Key": "Value"}))
+            .timeout(Duration::from_secs(1));
+        let clone = data.clone();
+
+        assert_eq!(clone.method(), data.method());
+        assert_eq!(clone.url(), data.url());
+        assert_eq!(clone.headers, data.headers);
+        assert_eq!(clone.timeout, data.timeout);
+        let (body, clone_body) = (data.body.unwrap(), clone.body.unwrap());
+        assert_eq!(body.as_ptr(), clone_body.as_ptr());
+    }
+
+    #[test]
+    fn request_data_header_replaces_any_casing() {
+        let data = RequestData::new(Method::Get, "tests/ping")
+            .header("Authorization", "Bearer old")
+            .header("X-Other", "1")
+            .header("authorization", "Bearer new");
+
+        assert_eq!(
+            data.headers,
+            vec![
+                ("authorization".to_string(), "Bearer new".to_string()),
+                ("X-Other".to_string(), "1".to_string()),
+            ]
+        );
+    }
+}
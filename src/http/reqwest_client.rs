@@ -1,27 +1,75 @@
+use crate::http::routing::BaseUrls;
 use crate::http::{
-    ClientAsync, ClientBuilder, ClientRequest, ClientRequestBuilder, Error, FromResponse, Method,
-    RequestData, ResponseBodyAsync, X_PM_APP_VERSION_HEADER,
+    ClientAsync, ClientBuilder, ClientRequest, ClientRequestBuilder, CookieJar, Error,
+    FromResponse, Method, MetricsHook, RedirectPolicy, RequestData, RequestMetrics,
+    ResponseBodyAsync, TlsVersion, DEFAULT_MAX_BODY_SIZE, RETRY_AFTER_HEADER,
+    X_PM_APP_VERSION_HEADER, X_REQUEST_ID_HEADER,
 };
 use crate::requests::APIError;
 use bytes::Bytes;
 use reqwest;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[cfg(not(feature = "async-traits"))]
-use std::future::Future;
-#[cfg(not(feature = "async-traits"))]
-use std::pin::Pin;
+use crate::http::BoxFuture;
 
 #[derive(Debug, Clone)]
 pub struct ReqwestClient {
     client: reqwest::Client,
-    base_url: String,
+    base_urls: BaseUrls,
+    cookies: Arc<ReqwestCookieStore>,
+    request_ids: bool,
+    metrics: Option<MetricsHook>,
+    max_body_size: usize,
+    debug: bool,
+}
+
+/// Cookie store which, unlike reqwest's own jar, can be exported.
+#[derive(Debug, Default)]
+struct ReqwestCookieStore(parking_lot::RwLock<cookie_store::CookieStore>);
+
+impl reqwest::cookie::CookieStore for ReqwestCookieStore {
+    fn set_cookies(
+        &self,
+        cookie_headers: &mut dyn Iterator<Item = &reqwest::header::HeaderValue>,
+        url: &reqwest::Url,
+    ) {
+        let cookies = cookie_headers.filter_map(|v| {
+            v.to_str()
+                .ok()
+                .and_then(|s| cookie_store::RawCookie::parse(s).ok())
+                .map(|c| c.into_owned())
+        });
+
+        self.0.write().store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &reqwest::Url) -> Option<reqwest::header::HeaderValue> {
+        let value = self
+            .0
+            .read()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if value.is_empty() {
+            return None;
+        }
+
+        reqwest::header::HeaderValue::from_str(&value).ok()
+    }
 }
 
 impl TryFrom<ClientBuilder> for ReqwestClient {
     type Error = anyhow::Error;
 
     fn try_from(value: ClientBuilder) -> Result<Self, Self::Error> {
+        use reqwest::redirect::Policy;
         use reqwest::tls::Version;
+        value.validate()?;
+
         let mut header_map = reqwest::header::HeaderMap::new();
         header_map.insert(
             X_PM_APP_VERSION_HEADER,
@@ -29,10 +77,29 @@ impl TryFrom<ClientBuilder> for ReqwestClient {
                 .map_err(|e| anyhow::anyhow!(e))?,
         );
 
+        // Request headers replace default headers with the same name.
+        for (header, header_value) in &value.default_headers {
+            header_map.insert(
+                reqwest::header::HeaderName::from_bytes(header.as_bytes())
+                    .map_err(|e| anyhow::anyhow!(e))?,
+                reqwest::header::HeaderValue::from_str(header_value)
+                    .map_err(|e| anyhow::anyhow!(e))?,
+            );
+        }
+
+        let cookies = if let Some(cookies) = &value.cookies {
+            Arc::new(ReqwestCookieStore(parking_lot::RwLock::new(
+                cookies.to_store()?,
+            )))
+        } else {
+            Arc::new(ReqwestCookieStore::default())
+        };
+
         let mut builder = reqwest::ClientBuilder::new();
 
         if let Some(proxy) = value.proxy_url {
-            let proxy = reqwest::Proxy::all(proxy.as_url())?;
+            let proxy = reqwest::Proxy::all(proxy.as_url())?
+                .no_proxy(reqwest::NoProxy::from_string(&value.no_proxy.join(",")));
             builder = builder.proxy(proxy);
         }
 
@@ -44,16 +111,47 @@ impl TryFrom<ClientBuilder> for ReqwestClient {
             builder = builder.timeout(d)
         }
 
+        if value.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge()
+        }
+
+        if let Some(d) = value.tcp_keepalive {
+            builder = builder.tcp_keepalive(d)
+        }
+
+        if let Some(d) = value.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(d)
+        }
+
         builder = builder
-            .min_tls_version(Version::TLS_1_2)
+            .min_tls_version(match value.min_tls_version {
+                TlsVersion::Tls1_2 => Version::TLS_1_2,
+                TlsVersion::Tls1_3 => Version::TLS_1_3,
+            })
             .https_only(!value.allow_http)
-            .cookie_store(true)
+            // Send header names as `X-Pm-Uid` rather than lower cased.
+            .http1_title_case_headers()
             .user_agent(value.user_agent)
             .default_headers(header_map);
 
+        if value.cookies_enabled {
+            builder = builder.cookie_provider(cookies.clone());
+        }
+
+        match value.redirect_policy {
+            Some(RedirectPolicy::None) => builder = builder.redirect(Policy::none()),
+            Some(RedirectPolicy::Limited(max)) => builder = builder.redirect(Policy::limited(max)),
+            None => {}
+        }
+
         Ok(Self {
             client: builder.build()?,
-            base_url: value.base_url,
+            base_urls: BaseUrls::new(value.base_url, value.alternative_urls),
+            cookies,
+            request_ids: value.request_ids,
+            metrics: value.metrics,
+            max_body_size: value.max_body_size,
+            debug: value.debug,
         })
     }
 }
@@ -88,31 +186,106 @@ impl From<reqwest::Error> for Error {
     }
 }
 
-struct ReqwestResponse(reqwest::Response);
+struct ReqwestResponse(reqwest::Response, usize);
+
+async fn read_body_limited(
+    mut response: reqwest::Response,
+    limit: usize,
+) -> crate::http::Result<Bytes> {
+    let limit_err = || {
+        Error::Request(anyhow::anyhow!(
+            "Response body exceeds limit of {limit} bytes"
+        ))
+    };
+
+    if response
+        .content_length()
+        .is_some_and(|len| len > limit as u64)
+    {
+        return Err(limit_err());
+    }
+
+    let mut body = bytes::BytesMut::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(limit_err());
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body.freeze())
+}
 
-pub struct ReqwestRequest(reqwest::RequestBuilder);
+pub struct ReqwestRequest {
+    builder: reqwest::RequestBuilder,
+    path: String,
+    request_id: Option<String>,
+    metrics: Option<RequestMetrics>,
+}
 
 impl ClientRequest for ReqwestRequest {
-    fn header(self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self {
-        Self(self.0.header(key.as_ref(), value.as_ref()))
+    fn header(mut self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        self.builder = self.builder.header(key.as_ref(), value.as_ref());
+        self
     }
 }
 
 impl ResponseBodyAsync for ReqwestResponse {
     type Body = Bytes;
 
+    fn header(&self, name: &str) -> Option<String> {
+        self.0
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    }
+
     #[cfg(not(feature = "async-traits"))]
-    fn get_body_async(self) -> Pin<Box<dyn Future<Output = crate::http::Result<Self::Body>>>> {
-        Box::pin(async {
-            let bytes = self.0.bytes().await?;
-            Ok(bytes)
-        })
+    fn get_body_async(self) -> BoxFuture<'static, crate::http::Result<Self::Body>> {
+        Box::pin(read_body_limited(self.0, self.1))
+    }
+
+    #[cfg(feature = "async-traits")]
+    async fn get_body_async(self) -> crate::http::Result<Self::Body> {
+        read_body_limited(self.0, self.1).await
+    }
+}
+
+/// Same as [`ReqwestResponse`], but logs the response body.
+struct ReqwestDebugResponse(reqwest::Response, usize);
+
+impl ReqwestDebugResponse {
+    async fn read_body(self) -> crate::http::Result<Bytes> {
+        let body = read_body_limited(self.0, self.1).await?;
+        log::debug!(
+            "Response Body ({} bytes): {}",
+            body.len(),
+            String::from_utf8_lossy(&body)
+        );
+        Ok(body)
+    }
+}
+
+impl ResponseBodyAsync for ReqwestDebugResponse {
+    type Body = Bytes;
+
+    fn header(&self, name: &str) -> Option<String> {
+        self.0
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn get_body_async(self) -> BoxFuture<'static, crate::http::Result<Self::Body>> {
+        Box::pin(self.read_body())
     }
 
     #[cfg(feature = "async-traits")]
     async fn get_body_async(self) -> crate::http::Result<Self::Body> {
-        let bytes = self.0.bytes().await?;
-        Ok(bytes)
+        self.read_body().await
     }
 }
 
@@ -120,7 +293,7 @@ impl ClientRequestBuilder for ReqwestClient {
     type Request = ReqwestRequest;
 
     fn new_request(&self, data: &RequestData) -> Self::Request {
-        let final_url = format!("{}/{}", self.base_url, data.url);
+        let final_url = format!("{}/{}", self.base_urls.active(), data.url);
 
         let mut request = match data.method {
             Method::Delete => self.client.delete(&final_url),
@@ -128,43 +301,157 @@ impl ClientRequestBuilder for ReqwestClient {
             Method::Put => self.client.put(&final_url),
             Method::Post => self.client.post(&final_url),
             Method::Patch => self.client.patch(&final_url),
+            Method::Head => self.client.head(&final_url),
         };
 
-        // Set headers.
+        // Set headers. reqwest only adds the client's default headers, including the app
+        // version, for names which the request does not set itself.
         for (header, value) in &data.headers {
             request = request.header(header, value);
         }
 
+        let request_id = self.request_ids.then(|| uuid::Uuid::new_v4().to_string());
+        if let Some(id) = &request_id {
+            request = request.header(X_REQUEST_ID_HEADER, id);
+        }
+
         if let Some(body) = &data.body {
             request = request.body(body.clone())
         }
 
-        ReqwestRequest(request)
+        if let Some(timeout) = data.timeout {
+            request = request.timeout(timeout)
+        }
+
+        ReqwestRequest {
+            builder: request,
+            path: data.url.clone(),
+            request_id,
+            metrics: RequestMetrics::new(&self.metrics, data),
+        }
     }
 }
 
 impl ReqwestClient {
+    /// Create a client from an already configured `client`. The [`ClientBuilder`] options which
+    /// configure the client are up to the caller, including sending the app version header
+    /// (`X-Pm-Appversion`) with every request. Cookies are managed by `client`, so
+    /// [`ReqwestClient::cookie_jar`] will not include them.
+    pub fn from_client(client: reqwest::Client, base_url: &str) -> Self {
+        Self {
+            client,
+            base_urls: BaseUrls::new(base_url.to_string(), Vec::new()),
+            cookies: Arc::new(ReqwestCookieStore::default()),
+            request_ids: false,
+            metrics: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            debug: false,
+        }
+    }
+
+    /// Export the current cookies of the client.
+    pub fn cookie_jar(&self) -> Result<CookieJar, anyhow::Error> {
+        CookieJar::from_store(&self.cookies.0.read())
+    }
+
     pub async fn direct_exec<R: FromResponse>(
         &self,
         r: ReqwestRequest,
     ) -> crate::http::Result<R::Output> {
-        let response = r.0.send().await?;
+        let timer = r.metrics.map(|m| m.start());
+        let result = self.exec_inner::<R>(r.builder, r.path, r.request_id).await;
+        if let Some(timer) = timer {
+            timer.end(&result);
+        }
+        result
+    }
+
+    async fn exec_inner<R: FromResponse>(
+        &self,
+        request: reqwest::RequestBuilder,
+        path: String,
+        request_id: Option<String>,
+    ) -> crate::http::Result<R::Output> {
+        let request = request.build()?;
+        let method = request.method().clone();
+        let started = Instant::now();
+        // Keep a copy to retry against the alternative base urls, not possible for streamed bodies.
+        let retry = request.try_clone();
+        let mut result = self.client.execute(request).await;
+
+        // Only requests which never reached the server can be sent again safely, anything else
+        // could apply a non-idempotent request twice.
+        if let Some(retry) = retry {
+            for (index, base_url) in self.base_urls.fallbacks() {
+                if !matches!(&result, Err(e) if e.is_connect()) {
+                    break;
+                }
+
+                let mut request = retry.try_clone().expect("request without body stream");
+                *request.url_mut() = reqwest::Url::parse(&format!("{base_url}/{path}"))
+                    .map_err(|e| Error::Request(e.into()))?;
+                log::debug!("Connection failed, retrying with alternative base url {base_url}");
+                result = self.client.execute(request).await;
+                if !matches!(&result, Err(e) if e.is_connect()) {
+                    self.base_urls.set_active(index);
+                }
+            }
+        }
+
+        let response = result?;
 
         let status = response.status().as_u16();
 
-        if status >= 400 {
-            let body = response
-                .bytes()
-                .await
-                .map_err(|_| Error::API(APIError::new(status)))?;
+        if self.debug {
+            log::debug!(
+                "{method} {}: {status} after {:?}",
+                response.url(),
+                started.elapsed()
+            );
+        }
+
+        // Redirects are only returned as is when the redirect policy does not follow them.
+        if (300..400).contains(&status) {
+            return Err(Error::Redirect(
+                response
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("Unknown url")
+                    .to_string(),
+                anyhow::anyhow!("Redirect with status {status} not followed"),
+            ));
+        }
 
-            return Err(Error::API(APIError::with_status_and_body(
-                status,
-                body.as_ref(),
-            )));
+        if status >= 400 {
+            let request_id = response
+                .headers()
+                .get(X_REQUEST_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+                .or(request_id);
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+
+            let err = match read_body_limited(response, self.max_body_size).await {
+                Ok(body) => APIError::with_status_and_body(status, body.as_ref()),
+                Err(_) => APIError::new(status),
+            };
+
+            return Err(err
+                .with_request_id(request_id)
+                .with_retry_after(retry_after.as_deref())
+                .into());
         }
 
-        R::from_response_async(ReqwestResponse(response)).await
+        if !self.debug {
+            R::from_response_async(ReqwestResponse(response, self.max_body_size)).await
+        } else {
+            R::from_response_async(ReqwestDebugResponse(response, self.max_body_size)).await
+        }
     }
 }
 
@@ -173,7 +460,7 @@ impl ClientAsync for ReqwestClient {
     fn execute_async<R: FromResponse>(
         &self,
         r: Self::Request,
-    ) -> Pin<Box<dyn Future<Output = crate::http::Result<R::Output>> + '_>> {
+    ) -> BoxFuture<'_, crate::http::Result<R::Output>> {
         Box::pin(async move { self.direct_exec::<R>(r).await })
     }
 
@@ -184,4 +471,38 @@ impl ClientAsync for ReqwestClient {
     ) -> crate::http::Result<R::Output> {
         self.direct_exec::<R>(request).await
     }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn execute_blocking<F, R>(&self, f: F) -> BoxFuture<'_, crate::http::Result<R>>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        Box::pin(async move {
+            tokio::task::spawn_blocking(f)
+                .await
+                .map_err(|e| Error::Other(e.into()))
+        })
+    }
+
+    #[cfg(feature = "async-traits")]
+    async fn execute_blocking<F, R>(&self, f: F) -> crate::http::Result<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        tokio::task::spawn_blocking(f)
+            .await
+            .map_err(|e| Error::Other(e.into()))
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    #[cfg(feature = "async-traits")]
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
 }
@@ -1,10 +1,10 @@
-use crate::http::{FromResponse, ResponseBodyAsync, ResponseBodySync, Result};
-use serde::de::DeserializeOwned;
 #[cfg(not(feature = "async-traits"))]
-use std::future::Future;
+use crate::http::BoxFuture;
+use crate::http::{Error, FromResponse, MaybeSend, ResponseBodyAsync, ResponseBodySync, Result};
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use std::io::Read;
 use std::marker::PhantomData;
-#[cfg(not(feature = "async-traits"))]
-use std::pin::Pin;
 
 #[derive(Copy, Clone)]
 pub struct NoResponse {}
@@ -17,14 +17,14 @@ impl FromResponse for NoResponse {
     }
 
     #[cfg(not(feature = "async-traits"))]
-    fn from_response_async<T: ResponseBodyAsync>(
+    fn from_response_async<T: ResponseBodyAsync + MaybeSend>(
         _: T,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output>>>> {
+    ) -> BoxFuture<'static, Result<Self::Output>> {
         Box::pin(async { Ok(()) })
     }
 
     #[cfg(feature = "async-traits")]
-    async fn from_response_async<T: ResponseBodyAsync>(_: T) -> Result<Self::Output> {
+    async fn from_response_async<T: ResponseBodyAsync + MaybeSend>(_: T) -> Result<Self::Output> {
         Ok(())
     }
 }
@@ -35,29 +35,132 @@ impl<T: DeserializeOwned> FromResponse for JsonResponse<T> {
     type Output = T;
 
     fn from_response_sync<R: ResponseBodySync>(response: R) -> Result<Self::Output> {
-        let body = response.get_body()?;
-        let r = serde_json::from_slice(body.as_ref())?;
-        Ok(r)
+        let mut reader = PrefixReader::new(response.get_body_reader()?);
+        serde_json::from_reader(&mut reader).map_err(|e| {
+            // Failures reading the body, such as exceeding the size limit, are not decode errors.
+            if e.is_io() {
+                Error::Request(e.into())
+            } else {
+                decode_error::<T>(e, &reader.prefix)
+            }
+        })
     }
 
     #[cfg(not(feature = "async-traits"))]
-    fn from_response_async<R: ResponseBodyAsync + 'static>(
+    fn from_response_async<R: ResponseBodyAsync + MaybeSend + 'static>(
         response: R,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output>>>> {
+    ) -> BoxFuture<'static, Result<Self::Output>> {
         Box::pin(async move {
             let body = response.get_body_async().await?;
-            let r = serde_json::from_slice(body.as_ref())?;
-            Ok(r)
+            serde_json::from_slice(body.as_ref()).map_err(|e| decode_error::<T>(e, body.as_ref()))
         })
     }
 
     #[cfg(feature = "async-traits")]
-    async fn from_response_async<R: ResponseBodyAsync + 'static>(
+    async fn from_response_async<R: ResponseBodyAsync + MaybeSend + 'static>(
         response: R,
     ) -> Result<Self::Output> {
         let body = response.get_body_async().await?;
-        let r = serde_json::from_slice(body.as_ref())?;
-        Ok(r)
+        serde_json::from_slice(body.as_ref()).map_err(|e| decode_error::<T>(e, body.as_ref()))
+    }
+}
+
+/// Number of bytes of the response body included in decode errors.
+const DECODE_ERROR_BODY_PREFIX: usize = 256;
+
+/// Fields whose values are never included in decode errors.
+const REDACTED_FIELDS: &[&str] = &[
+    "AccessToken",
+    "RefreshToken",
+    "UID",
+    "ServerProof",
+    "ServerEphemeral",
+    "Modulus",
+    "Salt",
+    "KeySalt",
+    "PrivateKey",
+    "Token",
+    "HumanVerificationToken",
+    "KeyPackets",
+    "Signature",
+];
+
+/// Create a decode error which records the target type and the start of the offending body.
+fn decode_error<T>(e: serde_json::Error, body: &[u8]) -> Error {
+    let prefix = &body[..body.len().min(DECODE_ERROR_BODY_PREFIX)];
+    Error::EncodeOrDecode(anyhow::anyhow!(
+        "Failed to decode {} ({e}) from response body '{}'",
+        std::any::type_name::<T>(),
+        redact_body(&String::from_utf8_lossy(prefix))
+    ))
+}
+
+/// Replace the string values of [`REDACTED_FIELDS`] in a, possibly truncated, json body.
+pub(crate) fn redact_body(body: &str) -> String {
+    const REDACTED: &str = "<redacted>";
+
+    let mut out = body.to_string();
+    for field in REDACTED_FIELDS {
+        let key = format!("\"{field}\"");
+        let mut search_from = 0;
+        while let Some(pos) = out[search_from..].find(&key) {
+            let after_key = search_from + pos + key.len();
+            let value = out[after_key..]
+                .trim_start()
+                .strip_prefix(':')
+                .map(|v| v.trim_start())
+                .and_then(|v| v.strip_prefix('"'));
+
+            let Some(value) = value else {
+                search_from = after_key;
+                continue;
+            };
+
+            let start = out.len() - value.len();
+            let end = string_end(value).map_or(out.len(), |e| start + e);
+            out.replace_range(start..end, REDACTED);
+            search_from = start + REDACTED.len();
+        }
+    }
+
+    out
+}
+
+/// Offset of the closing quote of a json string whose opening quote was already consumed.
+fn string_end(value: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Reader which keeps a copy of the first bytes read, to be reported on decode errors.
+struct PrefixReader<R> {
+    reader: R,
+    prefix: Vec<u8>,
+}
+
+impl<R: Read> PrefixReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            prefix: Vec::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for PrefixReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        let take = n.min(DECODE_ERROR_BODY_PREFIX - self.prefix.len());
+        self.prefix.extend_from_slice(&buf[..take]);
+        Ok(n)
     }
 }
 
@@ -73,9 +176,9 @@ impl FromResponse for StringResponse {
     }
 
     #[cfg(not(feature = "async-traits"))]
-    fn from_response_async<R: ResponseBodyAsync + 'static>(
+    fn from_response_async<R: ResponseBodyAsync + MaybeSend + 'static>(
         response: R,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output>>>> {
+    ) -> BoxFuture<'static, Result<Self::Output>> {
         Box::pin(async move {
             let body = response.get_body_async().await?;
             Ok(String::from_utf8_lossy(body.as_ref()).to_string())
@@ -83,10 +186,166 @@ impl FromResponse for StringResponse {
     }
 
     #[cfg(feature = "async-traits")]
-    async fn from_response_async<R: ResponseBodyAsync + 'static>(
+    async fn from_response_async<R: ResponseBodyAsync + MaybeSend + 'static>(
         response: R,
     ) -> Result<Self::Output> {
         let body = response.get_body_async().await?;
         Ok(String::from_utf8_lossy(body.as_ref()).to_string())
     }
 }
+
+/// Raw response body, for binary payloads which must not go through [`StringResponse`].
+#[derive(Copy, Clone)]
+pub struct BytesResponse {}
+
+impl FromResponse for BytesResponse {
+    type Output = Bytes;
+
+    fn from_response_sync<R: ResponseBodySync>(response: R) -> Result<Self::Output> {
+        Ok(response.get_body()?.into())
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn from_response_async<R: ResponseBodyAsync + MaybeSend + 'static>(
+        response: R,
+    ) -> BoxFuture<'static, Result<Self::Output>> {
+        Box::pin(async move { Ok(response.get_body_async().await?.into()) })
+    }
+
+    #[cfg(feature = "async-traits")]
+    async fn from_response_async<R: ResponseBodyAsync + MaybeSend + 'static>(
+        response: R,
+    ) -> Result<Self::Output> {
+        Ok(response.get_body_async().await?.into())
+    }
+}
+
+/// Response to a [`Method::Head`](crate::http::Method::Head) request. Only the headers are read,
+/// the output is the `Content-Length` reported by the server, if any.
+#[derive(Copy, Clone)]
+pub struct HeadResponse {}
+
+impl HeadResponse {
+    fn content_length(value: Option<String>) -> Option<u64> {
+        value.and_then(|v| v.trim().parse().ok())
+    }
+}
+
+impl FromResponse for HeadResponse {
+    type Output = Option<u64>;
+
+    fn from_response_sync<R: ResponseBodySync>(response: R) -> Result<Self::Output> {
+        Ok(Self::content_length(response.header("Content-Length")))
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn from_response_async<R: ResponseBodyAsync + MaybeSend + 'static>(
+        response: R,
+    ) -> BoxFuture<'static, Result<Self::Output>> {
+        let length = Self::content_length(response.header("Content-Length"));
+        Box::pin(async move { Ok(length) })
+    }
+
+    #[cfg(feature = "async-traits")]
+    async fn from_response_async<R: ResponseBodyAsync + MaybeSend + 'static>(
+        response: R,
+    ) -> Result<Self::Output> {
+        Ok(Self::content_length(response.header("Content-Length")))
+    }
+}
+
+/// Reader which fails once more than `limit` bytes have been read from `R`.
+#[allow(unused)] // Only used by http implementations.
+pub(crate) struct BodyLimitReader<R> {
+    reader: R,
+    remaining: usize,
+    limit: usize,
+}
+
+#[allow(unused)] // Only used by http implementations.
+impl<R: Read> BodyLimitReader<R> {
+    pub(crate) fn new(reader: R, limit: usize) -> Self {
+        Self {
+            reader,
+            remaining: limit,
+            limit,
+        }
+    }
+}
+
+impl<R: Read> Read for BodyLimitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Allow reading one byte past the limit to detect bodies which exceed it.
+        let max = buf.len().min(self.remaining.saturating_add(1));
+        let n = self.reader.read(&mut buf[..max])?;
+        if n > self.remaining {
+            return Err(std::io::Error::other(format!(
+                "Response body exceeds limit of {} bytes",
+                self.limit
+            )));
+        }
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_limit_reader() {
+        let mut out = Vec::new();
+        BodyLimitReader::new(&b"0123456789"[..], 10)
+            .read_to_end(&mut out)
+            .expect("body within limit");
+        assert_eq!(out.len(), 10);
+
+        let mut out = Vec::new();
+        assert!(BodyLimitReader::new(&b"0123456789"[..], 9)
+            .read_to_end(&mut out)
+            .is_err());
+    }
+
+    #[test]
+    fn decode_error_includes_type_and_redacted_body() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Target {
+            #[allow(unused)]
+            value: i32,
+        }
+
+        let body = br#"{"AccessToken": "secret\"token", "Code": 1000}"#;
+        let err = serde_json::from_slice::<Target>(body).unwrap_err();
+        let err = decode_error::<Target>(err, body).to_string();
+        assert!(err.contains("Target"));
+        assert!(err.contains(r#""AccessToken": "<redacted>", "Code": 1000"#));
+        assert!(!err.contains("secret"));
+
+        assert_eq!(redact_body(r#"{"UID":"abc"#), r#"{"UID":"<redacted>"#);
+        assert_eq!(redact_body(r#"{"UID":1}"#), r#"{"UID":1}"#);
+    }
+
+    #[test]
+    fn head_response_does_not_read_body() {
+        struct HeadersOnly;
+
+        impl ResponseBodySync for HeadersOnly {
+            type Body = Vec<u8>;
+
+            fn get_body(self) -> Result<Self::Body> {
+                panic!("The body of a HEAD response should not be read")
+            }
+
+            fn header(&self, name: &str) -> Option<String> {
+                name.eq_ignore_ascii_case("Content-Length")
+                    .then(|| "1024".to_string())
+            }
+        }
+
+        assert_eq!(
+            HeadResponse::from_response_sync(HeadersOnly).unwrap(),
+            Some(1024)
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Primary base url followed by its alternatives. The url of the last successful request is
+/// shared by all clones of a client, so that later requests go straight to it.
+#[derive(Debug, Clone)]
+#[allow(unused)] // Only used by http implementations.
+pub(crate) struct BaseUrls {
+    urls: Arc<Vec<String>>,
+    active: Arc<AtomicUsize>,
+}
+
+#[allow(unused)] // Only used by http implementations.
+impl BaseUrls {
+    pub(crate) fn new(primary: String, alternatives: Vec<String>) -> Self {
+        let mut urls = vec![primary];
+        urls.extend(alternatives);
+        Self {
+            urls: Arc::new(urls),
+            active: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Base url new requests should be made against.
+    pub(crate) fn active(&self) -> &str {
+        &self.urls[self.active.load(Ordering::Relaxed)]
+    }
+
+    /// Base urls to try after a connection error against the active one, in order.
+    pub(crate) fn fallbacks(&self) -> impl Iterator<Item = (usize, &str)> {
+        let start = self.active.load(Ordering::Relaxed);
+        let len = self.urls.len();
+        (1..len).map(move |i| {
+            let index = (start + i) % len;
+            (index, self.urls[index].as_str())
+        })
+    }
+
+    pub(crate) fn set_active(&self, index: usize) {
+        self.active.store(index, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_urls_fallbacks() {
+        let urls = BaseUrls::new("a".to_string(), vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(urls.active(), "a");
+        assert_eq!(
+            urls.fallbacks().collect::<Vec<_>>(),
+            vec![(1, "b"), (2, "c")]
+        );
+
+        urls.clone().set_active(2);
+        assert_eq!(urls.active(), "c");
+        assert_eq!(
+            urls.fallbacks().collect::<Vec<_>>(),
+            vec![(0, "a"), (1, "b")]
+        );
+
+        let urls = BaseUrls::new("a".to_string(), Vec::new());
+        assert_eq!(urls.fallbacks().count(), 0);
+    }
+}
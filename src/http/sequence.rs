@@ -1,11 +1,12 @@
-use crate::http::{ClientAsync, ClientSync, Error, FromResponse, Request};
+#[cfg(not(feature = "async-traits"))]
+use crate::http::BoxFuture;
+use crate::http::{ClientAsync, ClientSync, Error, FromResponse, MaybeSend, Request};
 use std::fmt::Debug;
 use std::future::Future;
-#[cfg(not(feature = "async-traits"))]
-use std::pin::Pin;
+use std::time::Duration;
 
 #[cfg(not(feature = "async-traits"))]
-type SequenceFuture<'a, O, E> = Pin<Box<dyn Future<Output = Result<O, E>> + 'a>>;
+type SequenceFuture<'a, O, E> = BoxFuture<'a, Result<O, E>>;
 
 /// Trait which can be use to link a sequence of request operations.
 pub trait Sequence {
@@ -20,7 +21,7 @@ pub trait Sequence {
         client: &'a T,
     ) -> SequenceFuture<'a, Self::Output, Self::Error>
     where
-        Self: 'a;
+        Self: 'a + MaybeSend;
 
     #[cfg(feature = "async-traits")]
     fn do_async<'a, T: ClientAsync>(
@@ -38,15 +39,70 @@ pub trait Sequence {
         MapSequence { c: self, f }
     }
 
-    fn map_err<E, F: FnOnce(Self::Error) -> Result<Self::Output, E>>(
+    /// Same as [`Sequence::map`], but `f` is expected to be CPU bound. On the async path it is
+    /// executed with [`ClientAsync::execute_blocking`] so it does not stall the executor.
+    fn map_blocking<O, E, F>(self, f: F) -> MapBlockingSequence<Self, F>
+    where
+        Self: Sized,
+        Self::Output: Send + 'static,
+        F: FnOnce(Self::Output) -> Result<O, E> + Send + 'static,
+        O: Send + 'static,
+        E: From<Self::Error> + From<Error> + Debug + Send + 'static,
+    {
+        MapBlockingSequence { c: self, f }
+    }
+
+    /// Convert the error of the sequence with `f`, leaving the output untouched.
+    ///
+    /// Note: Before version 0.15 this name belonged to the combinator which recovers from errors,
+    /// now [`Sequence::or_else`]. Closures which return a `Result` have to move to the latter.
+    fn map_err<E, F: FnOnce(Self::Error) -> E>(self, f: F) -> MapErrSequence<Self, F>
+    where
+        Self: Sized,
+        E: From<Error> + Debug,
+    {
+        MapErrSequence { c: self, f }
+    }
+
+    /// Give `f` the chance to recover from an error of the sequence. If `f` returns `Ok`, that
+    /// value becomes the output of the sequence.
+    fn or_else<E, F: FnOnce(Self::Error) -> Result<Self::Output, E>>(
         self,
         f: F,
-    ) -> MapErrSequence<Self, F>
+    ) -> OrElseSequence<Self, F>
     where
         Self: Sized,
         E: From<Self::Error> + From<Error> + Debug,
     {
-        MapErrSequence { c: self, f }
+        OrElseSequence { c: self, f }
+    }
+
+    /// Call `f` with a reference to the output of the sequence on success, before passing the
+    /// output through unchanged. Useful for tracing intermediate steps.
+    fn inspect<F: FnOnce(&Self::Output)>(self, f: F) -> InspectSequence<Self, F>
+    where
+        Self: Sized,
+    {
+        InspectSequence { c: self, f }
+    }
+
+    /// Wait for `duration` before running the sequence.
+    fn delay(self, duration: Duration) -> DelaySequence<Self>
+    where
+        Self: Sized,
+    {
+        DelaySequence { c: self, duration }
+    }
+
+    /// Fail with [`Error::Timeout`] if the sequence does not complete within `duration` on the
+    /// async path. The sync path can't interrupt a running request, so there the sequence runs
+    /// unchanged and is bound by the timeouts of the client or of its requests instead, see
+    /// [`RequestData::timeout`](crate::http::RequestData::timeout).
+    fn timeout(self, duration: Duration) -> TimeoutSequence<Self>
+    where
+        Self: Sized,
+    {
+        TimeoutSequence { c: self, duration }
     }
 
     fn state<SS, F>(self, f: F) -> SequenceWithState<Self, F>
@@ -91,11 +147,14 @@ impl<R: Request> Sequence for R {
     fn do_async<'a, T: ClientAsync>(
         self,
         client: &'a T,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + 'a>>
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
     where
-        Self: 'a,
+        Self: 'a + MaybeSend,
     {
-        Box::pin(async move { self.exec_async(client).await })
+        // Build the request before the future is created so it does not need to hold on to a
+        // reference of self.
+        let v = self.build(client);
+        Box::pin(async move { client.execute_async::<R::Response>(v).await })
     }
 
     #[cfg(feature = "async-traits")]
@@ -118,7 +177,9 @@ pub struct MapSequence<C, F> {
 
 impl<C, O, E, F> Sequence for MapSequence<C, F>
 where
+    F: MaybeSend,
     C: Sequence,
+    C: MaybeSend,
     F: FnOnce(C::Output) -> Result<O, E>,
     E: From<Error> + Debug + From<C::Error>,
 {
@@ -135,9 +196,9 @@ where
     fn do_async<'a, T: ClientAsync>(
         self,
         client: &'a T,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + 'a>>
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
     where
-        Self: 'a,
+        Self: 'a + MaybeSend,
     {
         Box::pin(async move {
             let v = self.c.do_async(client).await?;
@@ -168,6 +229,69 @@ where
     }
 }
 
+#[doc(hidden)]
+pub struct MapBlockingSequence<C, F> {
+    c: C,
+    f: F,
+}
+
+impl<C, O, E, F> Sequence for MapBlockingSequence<C, F>
+where
+    C: Sequence,
+    C: MaybeSend,
+    C::Output: Send + 'static,
+    F: FnOnce(C::Output) -> Result<O, E> + Send + 'static,
+    O: Send + 'static,
+    E: From<Error> + Debug + From<C::Error> + Send + 'static,
+{
+    type Output = O;
+    type Error = E;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        let v = self.c.do_sync(client)?;
+        let r = (self.f)(v)?;
+        Ok(r)
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(async move {
+            let v = self.c.do_async(client).await?;
+            let f = self.f;
+            let r = client.execute_blocking(move || f(v)).await??;
+            Ok(r)
+        })
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl Future<
+        Output = Result<
+            <MapBlockingSequence<C, F> as Sequence>::Output,
+            <MapBlockingSequence<C, F> as Sequence>::Error,
+        >,
+    > + 'a
+    where
+        F: 'a,
+        C: 'a,
+    {
+        async move {
+            let v = self.c.do_async(client).await?;
+            let f = self.f;
+            let r = client.execute_blocking(move || f(v)).await??;
+            Ok(r)
+        }
+    }
+}
+
 #[doc(hidden)]
 pub struct MapErrSequence<C, F> {
     c: C,
@@ -176,7 +300,59 @@ pub struct MapErrSequence<C, F> {
 
 impl<C, E, F> Sequence for MapErrSequence<C, F>
 where
+    F: MaybeSend,
     C: Sequence,
+    C: MaybeSend,
+    F: FnOnce(C::Error) -> E,
+    E: From<Error> + Debug,
+{
+    type Output = C::Output;
+    type Error = E;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        self.c.do_sync(client).map_err(self.f)
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(async move { self.c.do_async(client).await.map_err(self.f) })
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl Future<
+        Output = Result<
+            <MapErrSequence<C, F> as Sequence>::Output,
+            <MapErrSequence<C, F> as Sequence>::Error,
+        >,
+    > + 'a
+    where
+        F: 'a,
+        C: 'a,
+    {
+        async move { self.c.do_async(client).await.map_err(self.f) }
+    }
+}
+
+#[doc(hidden)]
+pub struct OrElseSequence<C, F> {
+    c: C,
+    f: F,
+}
+
+impl<C, E, F> Sequence for OrElseSequence<C, F>
+where
+    F: MaybeSend,
+    C: Sequence,
+    C: MaybeSend,
     F: FnOnce(C::Error) -> Result<C::Output, E>,
     E: From<Error> + Debug + From<C::Error>,
 {
@@ -194,9 +370,9 @@ where
     fn do_async<'a, T: ClientAsync>(
         self,
         client: &'a T,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + 'a>>
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
     where
-        Self: 'a,
+        Self: 'a + MaybeSend,
     {
         Box::pin(async move {
             match self.c.do_async(client).await {
@@ -212,8 +388,68 @@ where
         client: &'a T,
     ) -> impl Future<
         Output = Result<
-            <MapErrSequence<C, F> as Sequence>::Output,
-            <MapErrSequence<C, F> as Sequence>::Error,
+            <OrElseSequence<C, F> as Sequence>::Output,
+            <OrElseSequence<C, F> as Sequence>::Error,
+        >,
+    > + 'a
+    where
+        F: 'a,
+        C: 'a,
+    {
+        async move {
+            match self.c.do_async(client).await {
+                Ok(o) => Ok(o),
+                Err(e) => (self.f)(e),
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct InspectSequence<C, F> {
+    c: C,
+    f: F,
+}
+
+impl<C, F> Sequence for InspectSequence<C, F>
+where
+    F: MaybeSend,
+    C: Sequence,
+    C: MaybeSend,
+    F: FnOnce(&C::Output),
+{
+    type Output = C::Output;
+    type Error = C::Error;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        let v = self.c.do_sync(client)?;
+        (self.f)(&v);
+        Ok(v)
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(async move {
+            let v = self.c.do_async(client).await?;
+            (self.f)(&v);
+            Ok(v)
+        })
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl Future<
+        Output = Result<
+            <InspectSequence<C, F> as Sequence>::Output,
+            <InspectSequence<C, F> as Sequence>::Error,
         >,
     > + 'a
     where
@@ -222,12 +458,144 @@ where
     {
         async move {
             let v = self.c.do_async(client).await?;
-            let r = (self.f)(v)?;
-            Ok(r)
+            (self.f)(&v);
+            Ok(v)
         }
     }
 }
 
+#[doc(hidden)]
+pub struct DelaySequence<C> {
+    c: C,
+    duration: Duration,
+}
+
+impl<C> Sequence for DelaySequence<C>
+where
+    C: Sequence,
+    C: MaybeSend,
+{
+    type Output = C::Output;
+    type Error = C::Error;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        client.sleep(self.duration);
+        self.c.do_sync(client)
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(async move {
+            client.sleep(self.duration).await;
+            self.c.do_async(client).await
+        })
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl Future<
+        Output = Result<
+            <DelaySequence<C> as Sequence>::Output,
+            <DelaySequence<C> as Sequence>::Error,
+        >,
+    > + 'a
+    where
+        C: 'a,
+    {
+        async move {
+            client.sleep(self.duration).await;
+            self.c.do_async(client).await
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct TimeoutSequence<C> {
+    c: C,
+    duration: Duration,
+}
+
+impl<C> Sequence for TimeoutSequence<C>
+where
+    C: Sequence,
+    C: MaybeSend,
+{
+    type Output = C::Output;
+    type Error = C::Error;
+
+    fn do_sync<T: ClientSync>(self, client: &T) -> Result<Self::Output, Self::Error> {
+        self.c.do_sync(client)
+    }
+
+    #[cfg(not(feature = "async-traits"))]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
+    where
+        Self: 'a + MaybeSend,
+    {
+        Box::pin(race_timeout(
+            self.c.do_async(client),
+            client.sleep(self.duration),
+            self.duration,
+        ))
+    }
+
+    #[cfg(feature = "async-traits")]
+    fn do_async<'a, T: ClientAsync>(
+        self,
+        client: &'a T,
+    ) -> impl Future<
+        Output = Result<
+            <TimeoutSequence<C> as Sequence>::Output,
+            <TimeoutSequence<C> as Sequence>::Error,
+        >,
+    > + 'a
+    where
+        C: 'a,
+    {
+        race_timeout(
+            self.c.do_async(client),
+            client.sleep(self.duration),
+            self.duration,
+        )
+    }
+}
+
+/// Resolve to the result of `work`, or to [`Error::Timeout`] if `timer` completes first.
+async fn race_timeout<O, E: From<Error>>(
+    work: impl Future<Output = Result<O, E>>,
+    timer: impl Future<Output = ()>,
+    duration: Duration,
+) -> Result<O, E> {
+    use std::task::Poll;
+
+    let mut work = std::pin::pin!(work);
+    let mut timer = std::pin::pin!(timer);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(r) = work.as_mut().poll(cx) {
+            return Poll::Ready(r);
+        }
+        if timer.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Err(Error::Timeout(anyhow::anyhow!(
+                "Sequence did not complete within {duration:?}"
+            ))
+            .into()));
+        }
+        Poll::Pending
+    })
+    .await
+}
+
 #[doc(hidden)]
 pub struct SequenceWithState<S, F> {
     seq: S,
@@ -236,8 +604,11 @@ pub struct SequenceWithState<S, F> {
 
 impl<S, SS, F> Sequence for SequenceWithState<S, F>
 where
+    F: MaybeSend,
     S: Sequence,
     SS: Sequence,
+    S: MaybeSend,
+    SS: MaybeSend,
     <SS as Sequence>::Error: From<<S as Sequence>::Error> + From<Error> + Debug,
     F: FnOnce(S::Output) -> SS,
 {
@@ -254,9 +625,9 @@ where
     fn do_async<'a, T: ClientAsync>(
         self,
         client: &'a T,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + 'a>>
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
     where
-        Self: 'a,
+        Self: 'a + MaybeSend,
     {
         Box::pin(async move {
             let state = self.seq.do_async(client).await?;
@@ -301,7 +672,10 @@ impl<S, F> SequenceFromState<S, F> {
 
 impl<Seq, S, F> Sequence for SequenceFromState<S, F>
 where
+    S: MaybeSend,
+    F: MaybeSend,
     Seq: Sequence,
+    Seq: MaybeSend,
     F: FnOnce(S) -> Seq,
 {
     type Output = Seq::Output;
@@ -316,9 +690,9 @@ where
     fn do_async<'a, T: ClientAsync>(
         self,
         client: &'a T,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + 'a>>
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
     where
-        Self: 'a,
+        Self: 'a + MaybeSend,
     {
         Box::pin(async move {
             let seq = (self.f)(self.s);
@@ -354,8 +728,11 @@ pub struct SequenceChain<S, F> {
 
 impl<SS, S, F> Sequence for SequenceChain<S, F>
 where
+    F: MaybeSend,
     SS: Sequence<Error = S::Error>,
     S: Sequence,
+    SS: MaybeSend,
+    S: MaybeSend,
     F: FnOnce(S::Output) -> Result<SS, S::Error>,
     <SS as Sequence>::Error: From<S::Error> + Debug,
 {
@@ -372,9 +749,9 @@ where
     fn do_async<'a, T: ClientAsync>(
         self,
         client: &'a T,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + 'a>>
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
     where
-        Self: 'a,
+        Self: 'a + MaybeSend,
     {
         Box::pin(async move {
             let v = self.s.do_async(client).await?;
@@ -412,8 +789,11 @@ pub struct SequenceErrChain<S, F> {
 }
 impl<SS, S, F> Sequence for SequenceErrChain<S, F>
 where
+    F: MaybeSend,
     SS: Sequence<Output = S::Output, Error = S::Error>,
     S: Sequence,
+    SS: MaybeSend,
+    S: MaybeSend,
     F: FnOnce(S::Error) -> Result<SS, S::Error>,
     <SS as Sequence>::Error: From<S::Error> + Debug,
 {
@@ -434,18 +814,18 @@ where
     fn do_async<'a, T: ClientAsync>(
         self,
         client: &'a T,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + 'a>>
+    ) -> SequenceFuture<'a, Self::Output, Self::Error>
     where
-        Self: 'a,
+        Self: 'a + MaybeSend,
     {
         Box::pin(async move {
-            match self.s.do_async(client).await {
-                Err(e) => {
-                    let ss = (self.f)(e)?;
-                    ss.do_async(client).await
-                }
-                Ok(v) => Ok(v),
-            }
+            // Do not hold on to the result across the await below, it may not be Send.
+            let e = match self.s.do_async(client).await {
+                Err(e) => e,
+                Ok(v) => return Ok(v),
+            };
+            let ss = (self.f)(e)?;
+            ss.do_async(client).await
         })
     }
 
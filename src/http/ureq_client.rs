@@ -1,115 +1,286 @@
 //! UReq HTTP client implementation.
 
-use crate::http::X_PM_APP_VERSION_HEADER;
+use crate::http::proxy::bypass_proxy;
+use crate::http::routing::BaseUrls;
 use crate::http::{
-    ClientBuilder, ClientRequest, ClientRequestBuilder, ClientSync, Error, FromResponse, Method,
-    RequestData, ResponseBodySync,
+    BodyLimitReader, RedirectPolicy, TlsVersion, DEFAULT_MAX_BODY_SIZE, RETRY_AFTER_HEADER,
+    X_PM_APP_VERSION_HEADER, X_REQUEST_ID_HEADER,
+};
+use crate::http::{
+    ClientBuilder, ClientRequest, ClientRequestBuilder, ClientSync, CookieJar, Error, FromResponse,
+    Method, MetricsHook, RequestData, RequestMetrics, ResponseBodySync,
 };
 use crate::requests::APIError;
 use log::debug;
+use std::collections::HashMap;
 use std::io;
 use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use ureq;
 
 #[derive(Debug, Clone)]
 pub struct UReqClient {
     agent: ureq::Agent,
+    /// Agent without the proxy, for the base urls which match `no_proxy`. Only set when a proxy
+    /// is configured along with `no_proxy` patterns.
+    direct_agent: Option<ureq::Agent>,
+    no_proxy: Vec<String>,
+    /// Set when cookies are disabled. ureq always stores the cookies of an agent, so every
+    /// request gets a new agent built from these options instead. Connections are never reused,
+    /// which makes this cheap.
+    stateless: Option<Arc<ClientBuilder>>,
     app_version: String,
-    base_url: String,
+    base_urls: BaseUrls,
     debug: bool,
+    request_ids: bool,
+    metrics: Option<MetricsHook>,
+    max_body_size: usize,
+    default_headers: HashMap<String, String>,
 }
 
 impl TryFrom<ClientBuilder> for UReqClient {
     type Error = anyhow::Error;
 
     fn try_from(value: ClientBuilder) -> Result<Self, Self::Error> {
-        let mut builder = ureq::AgentBuilder::new();
+        value.validate()?;
 
-        if let Some(d) = value.request_timeout {
-            builder = builder.timeout(d);
-        }
+        let agent = build_agent(&value, true)?;
+        let direct_agent = if value.proxy_url.is_some() && !value.no_proxy.is_empty() {
+            Some(build_agent(&value, false)?)
+        } else {
+            None
+        };
+        let stateless = (!value.cookies_enabled).then(|| Arc::new(value.clone()));
+
+        Ok(Self {
+            agent,
+            direct_agent,
+            no_proxy: value.no_proxy,
+            stateless,
+            app_version: value.app_version,
+            base_urls: BaseUrls::new(value.base_url, value.alternative_urls),
+            debug: value.debug,
+            request_ids: value.request_ids,
+            metrics: value.metrics,
+            max_body_size: value.max_body_size,
+            default_headers: value.default_headers,
+        })
+    }
+}
+
+/// Build the agent for the options of `value`, with the configured proxy if `proxied` is set.
+fn build_agent(value: &ClientBuilder, proxied: bool) -> Result<ureq::Agent, anyhow::Error> {
+    let mut builder = ureq::AgentBuilder::new();
+
+    if let Some(d) = value.request_timeout {
+        builder = builder.timeout(d);
+    }
+
+    if let Some(d) = value.connect_timeout {
+        builder = builder.timeout_connect(d)
+    }
+
+    if let Some(d) = value.read_timeout {
+        builder = builder.timeout_read(d)
+    }
+
+    if let Some(d) = value.write_timeout {
+        builder = builder.timeout_write(d)
+    }
+
+    if let Some(proxy) = value.proxy_url.as_ref().filter(|_| proxied) {
+        builder = builder.proxy(ureq::Proxy::new(proxy.as_url())?);
+    }
 
-        if let Some(d) = value.connect_timeout {
-            builder = builder.timeout_connect(d)
+    if let Some(cookies) = value.cookies.as_ref().filter(|_| value.cookies_enabled) {
+        builder = builder.cookie_store(cookies.to_store()?);
+    }
+
+    match value.redirect_policy {
+        Some(RedirectPolicy::None) => builder = builder.redirects(0),
+        Some(RedirectPolicy::Limited(max)) => {
+            builder = builder.redirects(u32::try_from(max).unwrap_or(u32::MAX))
         }
+        None => {}
+    }
+
+    if !value.allow_http {
+        builder = builder.https_only(true)
+    }
+
+    // ureq only accepts TLS 1.2 and later by default.
+    if value.min_tls_version == TlsVersion::Tls1_3 {
+        builder = builder.tls_config(Arc::new(tls13_config()?));
+    }
+
+    Ok(builder
+        .user_agent(&value.user_agent)
+        // Never pool connections, so there are no stale ones to reuse after the client was idle.
+        .max_idle_connections(0)
+        .max_idle_connections_per_host(0)
+        .build())
+}
 
-        if let Some(proxy) = value.proxy_url {
-            let proxy = ureq::Proxy::new(proxy.as_url())?;
-            builder = builder.proxy(proxy);
+impl UReqClient {
+    /// Create a client from an already configured `agent`. The [`ClientBuilder`] options which
+    /// configure the agent, such as timeouts, proxies and cookies, are up to the caller.
+    pub fn from_agent(agent: ureq::Agent, base_url: &str, app_version: &str) -> Self {
+        Self {
+            agent,
+            direct_agent: None,
+            no_proxy: Vec::new(),
+            stateless: None,
+            app_version: app_version.to_string(),
+            base_urls: BaseUrls::new(base_url.to_string(), Vec::new()),
+            debug: false,
+            request_ids: false,
+            metrics: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            default_headers: HashMap::new(),
         }
+    }
 
-        if !value.allow_http {
-            builder = builder.https_only(true)
+    /// Agent with which to send the next request to `url`, skipping the proxy if `url` matches
+    /// the `no_proxy` patterns.
+    fn agent(&self, url: &str) -> ureq::Agent {
+        let bypass = bypass_proxy(&self.no_proxy, url);
+        match &self.stateless {
+            Some(options) => {
+                build_agent(options, !bypass).expect("Agent options were validated on creation")
+            }
+            None => match &self.direct_agent {
+                Some(agent) if bypass => agent.clone(),
+                _ => self.agent.clone(),
+            },
         }
+    }
 
-        let agent = builder
-            .user_agent(&value.user_agent)
-            .max_idle_connections(0)
-            .max_idle_connections_per_host(0)
-            .build();
+    /// Export the current cookies of the client.
+    pub fn cookie_jar(&self) -> Result<CookieJar, anyhow::Error> {
+        let Some(direct_agent) = &self.direct_agent else {
+            return CookieJar::from_store(&self.agent.cookie_store());
+        };
 
-        Ok(Self {
-            agent,
-            app_version: value.app_version,
-            base_url: value.base_url,
-            debug: value.debug,
-        })
+        // Both agents start with the same cookies, but a host only ever talks to one of them, so
+        // keep the cookies of each host from the agent which is used for it.
+        let (proxied, direct) = (self.agent.cookie_store(), direct_agent.cookie_store());
+        let bypass =
+            |c: &&cookie_store::Cookie| bypass_proxy(&self.no_proxy, &String::from(&c.domain));
+        let cookies = proxied
+            .iter_any()
+            .filter(|c| !bypass(c))
+            .chain(direct.iter_any().filter(bypass))
+            .cloned()
+            .map(Ok::<_, anyhow::Error>);
+        CookieJar::from_store(&cookie_store::CookieStore::from_cookies(cookies, true)?)
     }
 }
 
 impl From<ureq::Error> for Error {
     fn from(value: ureq::Error) -> Self {
-        match value {
-            ureq::Error::Status(status, response) => {
-                if let Ok(body) = safe_read_body(response) {
-                    return Error::API(APIError::with_status_and_body(status, &body));
-                }
+        error_from_ureq(value, DEFAULT_MAX_BODY_SIZE)
+    }
+}
 
-                Error::API(APIError::new(status))
-            }
-            ureq::Error::Transport(t) => match t.kind() {
-                ureq::ErrorKind::InvalidUrl => Error::Request(t.into()),
-                ureq::ErrorKind::UnknownScheme => Error::Request(t.into()),
-                ureq::ErrorKind::Dns => Error::Connection(t.into()),
-                ureq::ErrorKind::InsecureRequestHttpsOnly => Error::Request(t.into()),
-                ureq::ErrorKind::ConnectionFailed => Error::Connection(t.into()),
-                ureq::ErrorKind::TooManyRedirects => Error::Redirect(
-                    t.url()
-                        .map(|u| u.to_string())
-                        .unwrap_or("Unknown url".to_string()),
-                    t.into(),
-                ),
-                ureq::ErrorKind::BadStatus => Error::Request(t.into()),
-                ureq::ErrorKind::BadHeader => Error::Request(t.into()),
-                ureq::ErrorKind::Io => Error::Connection(t.into()),
-                ureq::ErrorKind::InvalidProxyUrl => Error::Connection(t.into()),
-                ureq::ErrorKind::ProxyConnect => Error::Connection(t.into()),
-                ureq::ErrorKind::ProxyUnauthorized => Error::Connection(t.into()),
-                ureq::ErrorKind::HTTP => Error::Request(t.into()),
-            },
+/// Convert `value`, reading at most `max_body_size` bytes of the body of error responses.
+fn error_from_ureq(value: ureq::Error, max_body_size: usize) -> Error {
+    match value {
+        ureq::Error::Status(status, response) => {
+            let request_id = response.header(X_REQUEST_ID_HEADER).map(|v| v.to_string());
+            let retry_after = response.header(RETRY_AFTER_HEADER).map(|v| v.to_string());
+            let err = match safe_read_body(response, max_body_size) {
+                Ok(body) => APIError::with_status_and_body(status, &body),
+                Err(_) => APIError::new(status),
+            };
+
+            err.with_request_id(request_id)
+                .with_retry_after(retry_after.as_deref())
+                .into()
         }
+        ureq::Error::Transport(t) => match t.kind() {
+            ureq::ErrorKind::InvalidUrl => Error::Request(t.into()),
+            ureq::ErrorKind::UnknownScheme => Error::Request(t.into()),
+            ureq::ErrorKind::Dns => Error::Connection(t.into()),
+            ureq::ErrorKind::InsecureRequestHttpsOnly => Error::Request(t.into()),
+            ureq::ErrorKind::ConnectionFailed => Error::Connection(t.into()),
+            ureq::ErrorKind::TooManyRedirects => Error::Redirect(
+                t.url()
+                    .map(|u| u.to_string())
+                    .unwrap_or("Unknown url".to_string()),
+                t.into(),
+            ),
+            ureq::ErrorKind::BadStatus => Error::Request(t.into()),
+            ureq::ErrorKind::BadHeader => Error::Request(t.into()),
+            ureq::ErrorKind::Io if is_timeout(&t) => Error::Timeout(t.into()),
+            ureq::ErrorKind::Io => Error::Connection(t.into()),
+            ureq::ErrorKind::InvalidProxyUrl => Error::Connection(t.into()),
+            ureq::ErrorKind::ProxyConnect => Error::Connection(t.into()),
+            ureq::ErrorKind::ProxyUnauthorized => Error::Connection(t.into()),
+            ureq::ErrorKind::HTTP => Error::Request(t.into()),
+        },
     }
 }
 
-struct UReqResponse(ureq::Response);
+/// Rustls configuration equivalent to ureq's default, but restricted to TLS 1.3.
+fn tls13_config() -> Result<rustls::ClientConfig, anyhow::Error> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+
+    Ok(rustls::ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+fn is_timeout(t: &ureq::Transport) -> bool {
+    std::error::Error::source(t)
+        .and_then(|e| e.downcast_ref::<io::Error>())
+        .map(|e| {
+            matches!(
+                e.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            )
+        })
+        .unwrap_or(false)
+}
+
+struct UReqResponse(ureq::Response, usize);
 
 impl ResponseBodySync for UReqResponse {
     type Body = Vec<u8>;
 
     fn get_body(self) -> crate::http::Result<Self::Body> {
-        let body = safe_read_body(self.0)
+        let body = safe_read_body(self.0, self.1)
             .map_err(|e| Error::Request(anyhow::anyhow!("Failed to read response body {e}")))?;
         Ok(body)
     }
+
+    fn header(&self, name: &str) -> Option<String> {
+        self.0.header(name).map(str::to_string)
+    }
+
+    fn get_body_reader(self) -> crate::http::Result<Box<dyn Read>> {
+        Ok(Box::new(BodyLimitReader::new(self.0.into_reader(), self.1)))
+    }
 }
 
-struct UReqDebugResponse(ureq::Response);
+struct UReqDebugResponse(ureq::Response, usize);
 
 impl ResponseBodySync for UReqDebugResponse {
     type Body = Vec<u8>;
 
     fn get_body(self) -> crate::http::Result<Self::Body> {
-        let body = safe_read_body(self.0)
+        let body = safe_read_body(self.0, self.1)
             .map_err(|e| Error::Request(anyhow::anyhow!("Failed to read response body {e}")))?;
 
         let body_str = String::from_utf8_lossy(&body);
@@ -117,11 +288,20 @@ impl ResponseBodySync for UReqDebugResponse {
 
         Ok(body)
     }
+
+    fn header(&self, name: &str) -> Option<String> {
+        self.0.header(name).map(str::to_string)
+    }
 }
 
 pub struct UReqRequest {
     request: ureq::Request,
+    path: String,
+    timeout: Option<Duration>,
     body: Option<bytes::Bytes>,
+    request_id: Option<String>,
+    metrics: Option<RequestMetrics>,
+    cancel: Option<Arc<AtomicBool>>,
 }
 
 impl ClientRequest for UReqRequest {
@@ -135,47 +315,155 @@ impl ClientRequestBuilder for UReqClient {
     type Request = UReqRequest;
 
     fn new_request(&self, request: &RequestData) -> Self::Request {
-        let final_url = format!("{}/{}", self.base_url, request.url);
+        let final_url = format!("{}/{}", self.base_urls.active(), request.url);
+        let agent = self.agent(&final_url);
         let mut ureq_request = match request.method {
-            Method::Delete => self.agent.delete(&final_url),
-            Method::Get => self.agent.get(&final_url),
-            Method::Put => self.agent.put(&final_url),
-            Method::Post => self.agent.post(&final_url),
-            Method::Patch => self.agent.patch(&final_url),
+            Method::Delete => agent.delete(&final_url),
+            Method::Get => agent.get(&final_url),
+            Method::Put => agent.put(&final_url),
+            Method::Post => agent.post(&final_url),
+            Method::Patch => agent.patch(&final_url),
+            Method::Head => agent.head(&final_url),
         };
 
-        // Set app version.
-        ureq_request = ureq_request.set(X_PM_APP_VERSION_HEADER, &self.app_version);
+        // Set app version, unless overridden by the request. ureq keeps repeated `X-` headers
+        // instead of replacing them, so both values would be sent otherwise.
+        if request.header_value(X_PM_APP_VERSION_HEADER).is_none() {
+            ureq_request = ureq_request.set(X_PM_APP_VERSION_HEADER, &self.app_version);
+        }
+
+        // Set default headers, unless overridden by the request's own headers.
+        for (header, value) in &self.default_headers {
+            if request.header_value(header).is_none() {
+                ureq_request = ureq_request.set(header, value);
+            }
+        }
 
         // Set headers.
         for (header, value) in &request.headers {
             ureq_request = ureq_request.set(header, value);
         }
 
+        let request_id = self.request_ids.then(|| uuid::Uuid::new_v4().to_string());
+        if let Some(id) = &request_id {
+            ureq_request = ureq_request.set(X_REQUEST_ID_HEADER, id);
+        }
+
+        if let Some(timeout) = request.timeout {
+            ureq_request = ureq_request.timeout(timeout);
+        }
+
         Self::Request {
             request: ureq_request,
+            path: request.url.clone(),
+            timeout: request.timeout,
             body: request.body.clone(),
+            request_id,
+            metrics: RequestMetrics::new(&self.metrics, request),
+            cancel: request.cancel.clone(),
         }
     }
 }
 
 impl ClientSync for UReqClient {
-    fn execute<R: FromResponse>(&self, request: Self::Request) -> Result<R::Output, Error> {
-        let ureq_response = if let Some(body) = request.body {
-            request.request.send_bytes(body.as_ref())?
-        } else {
-            request.request.call()?
+    fn execute<R: FromResponse>(&self, mut request: Self::Request) -> Result<R::Output, Error> {
+        let timer = request.metrics.take().map(|m| m.start());
+        let result = self.execute_inner::<R>(request);
+        if let Some(timer) = timer {
+            timer.end(&result);
+        }
+        result
+    }
+}
+
+/// Whether the request failed before reaching the server, in which case it can be sent to an
+/// alternative base url without risking applying a non-idempotent request twice.
+fn is_connect_error(e: &ureq::Error) -> bool {
+    matches!(
+        e,
+        ureq::Error::Transport(t)
+            if matches!(t.kind(), ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed)
+    )
+}
+
+impl UReqClient {
+    fn execute_inner<R: FromResponse>(&self, request: UReqRequest) -> Result<R::Output, Error> {
+        let check_cancelled = || match &request.cancel {
+            Some(flag) if flag.load(Ordering::SeqCst) => {
+                Err(Error::Connection(anyhow::anyhow!("Request cancelled")))
+            }
+            _ => Ok(()),
         };
+        // The ureq error is kept as is to tell connect failures apart before converting it.
+        #[allow(clippy::result_large_err)]
+        let send = |r: ureq::Request| -> Result<ureq::Response, ureq::Error> {
+            if let Some(body) = &request.body {
+                r.send_bytes(body.as_ref())
+            } else {
+                r.call()
+            }
+        };
+
+        check_cancelled()?;
+        let mut result = send(request.request.clone());
+        for (index, base_url) in self.base_urls.fallbacks() {
+            if !matches!(&result, Err(e) if is_connect_error(e)) {
+                break;
+            }
+
+            check_cancelled()?;
+            debug!("Connection failed, retrying with alternative base url {base_url}");
+            result = send(self.rebase(&request, base_url));
+            if !matches!(&result, Err(e) if is_connect_error(e)) {
+                self.base_urls.set_active(index);
+            }
+        }
+
+        let ureq_response = result.map_err(|e| {
+            error_from_ureq(e, self.max_body_size).or_request_id(request.request_id)
+        })?;
+        check_cancelled()?;
+
+        // Redirects are only returned as is when the redirect policy does not follow them.
+        if (300..400).contains(&ureq_response.status()) {
+            return Err(Error::Redirect(
+                ureq_response
+                    .header("location")
+                    .unwrap_or("Unknown url")
+                    .to_string(),
+                anyhow::anyhow!(
+                    "Redirect with status {} not followed",
+                    ureq_response.status()
+                ),
+            ));
+        }
 
         if !self.debug {
-            R::from_response_sync(UReqResponse(ureq_response))
+            R::from_response_sync(UReqResponse(ureq_response, self.max_body_size))
         } else {
-            R::from_response_sync(UReqDebugResponse(ureq_response))
+            R::from_response_sync(UReqDebugResponse(ureq_response, self.max_body_size))
         }
     }
+
+    /// Copy `request` with its url pointing to `base_url`.
+    fn rebase(&self, request: &UReqRequest, base_url: &str) -> ureq::Request {
+        let url = format!("{base_url}/{}", request.path);
+        let mut rebased = self.agent(&url).request(request.request.method(), &url);
+        for header in request.request.header_names() {
+            if let Some(value) = request.request.header(&header) {
+                rebased = rebased.set(&header, value);
+            }
+        }
+
+        if let Some(timeout) = request.timeout {
+            rebased = rebased.timeout(timeout);
+        }
+
+        rebased
+    }
 }
 
-fn safe_read_body(response: ureq::Response) -> Result<Vec<u8>, io::Error> {
+fn safe_read_body(response: ureq::Response, limit: usize) -> Result<Vec<u8>, io::Error> {
     let mut vec = vec![];
 
     if let Some(length) = response.header("Content-Length") {
@@ -183,14 +471,11 @@ fn safe_read_body(response: ureq::Response) -> Result<Vec<u8>, io::Error> {
             if len == 0 {
                 return Ok(vec![]);
             }
-            vec.reserve(len);
+            vec.reserve(len.min(limit));
         }
     }
 
-    let _ = response
-        .into_reader()
-        .take(10_000_000)
-        .read_to_end(&mut vec)?;
+    BodyLimitReader::new(response.into_reader(), limit).read_to_end(&mut vec)?;
 
     Ok(vec)
 }
@@ -54,6 +54,10 @@
 //!         SessionType::AwaitingTotp(t) => {
 //!             t.submit_totp("000000").do_async(&client).await.unwrap()
 //!         }
+//!         // Login needs a human verification, see `HvChallenge`.
+//!         SessionType::HumanVerificationRequired(c) => {
+//!             panic!("human verification required: {:?}", c.methods())
+//!         }
 //!     };
 //!
 //!     // session is now authenticated and can access the rest of the API.
@@ -81,6 +85,10 @@
 //!         SessionType::AwaitingTotp(t) => {
 //!             t.submit_totp("000000").do_sync(&client).unwrap()
 //!         }
+//!         // Login needs a human verification, see `HvChallenge`.
+//!         SessionType::HumanVerificationRequired(c) => {
+//!             panic!("human verification required: {:?}", c.methods())
+//!         }
 //!     };
 //!
 //!     // session is now authenticated and can access the rest of the API.
@@ -1,10 +1,46 @@
-use crate::domain::{HumanVerificationLoginData, SecretString, UserUid};
+use crate::domain::{
+    HumanVerificationLoginData, RefreshParams, SecretString, SessionInfo, SessionScope, UserId,
+    UserUid,
+};
 use crate::http;
 use crate::http::{RequestData, X_PM_HUMAN_VERIFICATION_TOKEN, X_PM_HUMAN_VERIFICATION_TOKEN_TYPE};
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
 use serde_repr::Deserialize_repr;
 use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+/// Establish an unauthenticated session. Its UID and access token authenticate the requests of
+/// the login flow, while the cookies it sets are kept by the client.
+#[doc(hidden)]
+pub struct GetUnauthSessionRequest;
+
+#[doc(hidden)]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct UnauthSessionResponse {
+    #[serde(rename = "UID")]
+    pub uid: UserUid,
+    #[serde(deserialize_with = "secret_string")]
+    pub access_token: SecretString,
+}
+
+/// Deserialize a string which must not show up in debug output.
+fn secret_string<'de, D>(deserializer: D) -> Result<SecretString, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    String::deserialize(deserializer).map(SecretString::new)
+}
+
+impl http::RequestDesc for GetUnauthSessionRequest {
+    type Output = UnauthSessionResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Post, "auth/v4/sessions")
+    }
+}
 
 #[doc(hidden)]
 #[derive(Serialize)]
@@ -78,23 +114,31 @@ pub struct AuthResponse {
     pub refresh_token: String,
     pub server_proof: String,
     pub scope: String,
-    #[serde(rename = "2FA")]
+    /// Lifetime of the access token in seconds.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    /// Defaults to no 2FA when missing.
+    #[serde(rename = "2FA", default)]
     pub tfa: TFAInfo,
+    /// Defaults to [`PasswordMode::One`] when missing.
+    #[serde(default)]
     pub password_mode: PasswordMode,
 }
 
 #[doc(hidden)]
-#[derive(Deserialize_repr, Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Deserialize_repr, Copy, Clone, Eq, PartialEq, Debug, Default)]
 #[repr(u8)]
 pub enum PasswordMode {
+    #[default]
     One = 1,
     Two = 2,
 }
 
 #[doc(hidden)]
-#[derive(Deserialize_repr, Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Deserialize_repr, Copy, Clone, Eq, PartialEq, Debug, Default)]
 #[repr(u8)]
 pub enum TFAStatus {
+    #[default]
     None = 0,
     Totp = 1,
     FIDO2 = 2,
@@ -102,7 +146,7 @@ pub enum TFAStatus {
 }
 
 #[doc(hidden)]
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 #[serde(rename_all = "PascalCase")]
 pub struct TFAInfo {
     pub enabled: TFAStatus,
@@ -121,7 +165,7 @@ pub struct FIDOKey<'a> {
 }
 
 #[doc(hidden)]
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 #[serde(rename_all = "PascalCase")]
 pub struct FIDO2Info {
     pub authentication_options: serde_json::Value,
@@ -171,9 +215,17 @@ impl<'a> TOTPRequest<'a> {
     }
 }
 
+#[doc(hidden)]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct TOTPResponse {
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
 impl<'a> http::RequestDesc for TOTPRequest<'a> {
-    type Output = ();
-    type Response = http::NoResponse;
+    type Output = TOTPResponse;
+    type Response = http::JsonResponse<Self::Output>;
 
     fn build(&self) -> RequestData {
         RequestData::new(http::Method::Post, "auth/v4/2fa").json(TFAAuth {
@@ -184,11 +236,30 @@ impl<'a> http::RequestDesc for TOTPRequest<'a> {
 }
 
 #[doc(hidden)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct UserAuth {
     pub uid: Secret<UserUid>,
     pub access_token: SecretString,
     pub refresh_token: SecretString,
+    pub scope: SessionScope,
+    /// When the access token expires, if the server reported its lifetime.
+    pub expires_at: Option<Instant>,
+    /// Id of the user, if known. Unlike the UID it is not secret and stays the same across
+    /// sessions, but only the login response carries it.
+    pub user_id: Option<UserId>,
+}
+
+impl std::fmt::Debug for UserAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UserAuth")
+            .field("uid", &"[REDACTED]")
+            .field("access_token", &"[REDACTED]")
+            .field("refresh_token", &"[REDACTED]")
+            .field("scope", &self.scope)
+            .field("expires_at", &self.expires_at)
+            .field("user_id", &self.user_id)
+            .finish()
+    }
 }
 
 impl UserAuth {
@@ -197,6 +268,9 @@ impl UserAuth {
             uid: Secret::new(UserUid(auth.uid)),
             access_token: SecretString::new(auth.access_token),
             refresh_token: SecretString::new(auth.refresh_token),
+            scope: SessionScope::from_scope_str(&auth.scope),
+            expires_at: expires_at(auth.expires_in),
+            user_id: Some(UserId(auth.user_id)),
         }
     }
 
@@ -205,10 +279,17 @@ impl UserAuth {
             uid: Secret::new(UserUid(auth.uid)),
             access_token: SecretString::new(auth.access_token),
             refresh_token: SecretString::new(auth.refresh_token),
+            scope: SessionScope::from_scope_str(&auth.scope),
+            expires_at: expires_at(auth.expires_in),
+            user_id: None,
         }
     }
 }
 
+fn expires_at(expires_in: Option<u64>) -> Option<Instant> {
+    expires_in.map(|secs| Instant::now() + Duration::from_secs(secs))
+}
+
 #[doc(hidden)]
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -232,16 +313,30 @@ pub struct AuthRefreshResponse {
     pub access_token: String,
     pub refresh_token: String,
     pub scope: String,
+    /// Lifetime of the access token in seconds.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
 }
 
 pub struct AuthRefreshRequest<'a> {
     uid: &'a UserUid,
     token: &'a str,
+    params: Option<&'a RefreshParams>,
 }
 
 impl<'a> AuthRefreshRequest<'a> {
     pub fn new(uid: &'a UserUid, token: &'a str) -> Self {
-        Self { uid, token }
+        Self {
+            uid,
+            token,
+            params: None,
+        }
+    }
+
+    /// Send `params` instead of the [`RefreshParams::default`] ones.
+    pub fn with_params(mut self, params: &'a RefreshParams) -> Self {
+        self.params = Some(params);
+        self
     }
 }
 
@@ -250,12 +345,21 @@ impl<'a> http::RequestDesc for AuthRefreshRequest<'a> {
     type Response = http::JsonResponse<Self::Output>;
 
     fn build(&self) -> RequestData {
+        let default_params;
+        let params = match self.params {
+            Some(p) => p,
+            None => {
+                default_params = RefreshParams::default();
+                &default_params
+            }
+        };
+
         RequestData::new(http::Method::Post, "auth/v4/refresh").json(AuthRefresh {
             uid: &self.uid.0,
             refresh_token: self.token,
-            grant_type: "refresh_token",
-            response_type: "token",
-            redirect_uri: "https://protonmail.ch/",
+            grant_type: &params.grant_type,
+            response_type: &params.response_type,
+            redirect_uri: &params.redirect_uri,
         })
     }
 }
@@ -271,6 +375,126 @@ impl http::RequestDesc for LogoutRequest {
     }
 }
 
+pub struct GetSessionsRequest {}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GetSessionsResponse {
+    pub sessions: Vec<SessionInfo>,
+}
+
+impl http::RequestDesc for GetSessionsRequest {
+    type Output = GetSessionsResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Get, "auth/v4/sessions")
+    }
+}
+
+pub struct RevokeAllSessionsRequest {}
+
+impl http::RequestDesc for RevokeAllSessionsRequest {
+    type Output = ();
+    type Response = http::NoResponse;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Delete, "auth/v4/sessions")
+    }
+}
+
+pub struct RevokeSessionRequest<'a> {
+    uid: &'a UserUid,
+}
+
+impl<'a> RevokeSessionRequest<'a> {
+    pub fn new(uid: &'a UserUid) -> Self {
+        Self { uid }
+    }
+}
+
+impl<'a> http::RequestDesc for RevokeSessionRequest<'a> {
+    type Output = ();
+    type Response = http::NoResponse;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(
+            http::Method::Delete,
+            format!("auth/v4/sessions/{}", self.uid),
+        )
+    }
+}
+
+/// Fork the current session for the client `child_client_id`, which can then take it over with
+/// [`ConsumeForkRequest`].
+#[doc(hidden)]
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ForkSessionRequest<'a> {
+    #[serde(rename = "ChildClientID")]
+    pub child_client_id: &'a str,
+    pub independent: u8,
+}
+
+impl<'a> ForkSessionRequest<'a> {
+    pub fn new(child_client_id: &'a str) -> Self {
+        Self {
+            child_client_id,
+            independent: 0,
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ForkSessionResponse {
+    pub selector: String,
+}
+
+impl<'a> http::RequestDesc for ForkSessionRequest<'a> {
+    type Output = ForkSessionResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Post, "auth/v4/sessions/forks").json(self)
+    }
+}
+
+pub struct ConsumeForkRequest<'a> {
+    selector: &'a str,
+}
+
+impl<'a> ConsumeForkRequest<'a> {
+    pub fn new(selector: &'a str) -> Self {
+        Self { selector }
+    }
+}
+
+/// Auth material of a forked session. The access token is obtained by refreshing the session.
+#[doc(hidden)]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ConsumeForkResponse {
+    #[serde(rename = "UID")]
+    pub uid: UserUid,
+    #[serde(deserialize_with = "secret_string")]
+    pub refresh_token: SecretString,
+}
+
+impl<'a> http::RequestDesc for ConsumeForkRequest<'a> {
+    type Output = ConsumeForkResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(
+            http::Method::Get,
+            format!("auth/v4/sessions/forks/{}", self.selector),
+        )
+    }
+}
+
 pub struct CaptchaRequest<'a> {
     token: &'a str,
     force_web: bool,
@@ -296,3 +520,111 @@ impl<'a> http::RequestDesc for CaptchaRequest<'a> {
         RequestData::new(http::Method::Get, url)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn fork_session_requests() {
+        use http::RequestDesc;
+
+        let data = ForkSessionRequest::new("child").build();
+        assert_eq!(data.method(), http::Method::Post);
+        assert_eq!(data.url(), "auth/v4/sessions/forks");
+
+        let data = ConsumeForkRequest::new("selector").build();
+        assert_eq!(data.method(), http::Method::Get);
+        assert_eq!(data.url(), "auth/v4/sessions/forks/selector");
+
+        let response = serde_json::from_str::<ConsumeForkResponse>(
+            r#"{"UID": "uid", "RefreshToken": "token", "Scopes": ["full"], "LocalID": 0}"#,
+        )
+        .unwrap();
+        assert_eq!(response.uid, UserUid::from("uid"));
+        assert_eq!(response.refresh_token.expose_secret(), "token");
+        let debug = format!("{response:?}");
+        assert!(
+            !debug.contains("\"uid\"") && !debug.contains("\"token\""),
+            "{debug}"
+        );
+    }
+
+    #[test]
+    fn auth_refresh_request_params() {
+        use http::RequestDesc;
+
+        let uid = UserUid::from("uid");
+        let body = |data: RequestData| -> serde_json::Value {
+            serde_json::from_slice(data.body().unwrap()).unwrap()
+        };
+
+        let data = body(AuthRefreshRequest::new(&uid, "token").build());
+        assert_eq!(data["RedirectURI"], "https://protonmail.ch/");
+        assert_eq!(data["ResponseType"], "token");
+        assert_eq!(data["GrantType"], "refresh_token");
+
+        let params = RefreshParams {
+            redirect_uri: "https://example.com/".to_string(),
+            ..Default::default()
+        };
+        let data = body(
+            AuthRefreshRequest::new(&uid, "token")
+                .with_params(&params)
+                .build(),
+        );
+        assert_eq!(data["RedirectURI"], "https://example.com/");
+        assert_eq!(data["RefreshToken"], "token");
+    }
+
+    #[test]
+    fn unauth_session_response() {
+        let response = serde_json::from_str::<UnauthSessionResponse>(
+            r#"{"Code": 1000, "UID": "uid", "AccessToken": "access", "RefreshToken": "refresh", "Scopes": []}"#,
+        )
+        .unwrap();
+        assert_eq!(response.uid, UserUid::from("uid"));
+        assert_eq!(response.access_token.expose_secret(), "access");
+        assert!(!format!("{response:?}").contains("\"access\""));
+    }
+
+    #[test]
+    fn auth_response_without_tfa_and_password_mode() {
+        let response = serde_json::from_str::<AuthResponse>(
+            r#"{
+                "UserID": "user_id",
+                "UID": "uid",
+                "AccessToken": "access",
+                "RefreshToken": "refresh",
+                "ServerProof": "proof",
+                "Scope": "full"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(response.tfa.enabled, TFAStatus::None);
+        assert_eq!(response.password_mode, PasswordMode::One);
+    }
+
+    #[test]
+    fn auth_refresh_response_without_token_type() {
+        use secrecy::ExposeSecret;
+
+        let response = serde_json::from_str::<AuthRefreshResponse>(
+            r#"{
+                "Code": 1000,
+                "UID": "uid",
+                "AccessToken": "access",
+                "RefreshToken": "refresh",
+                "Scope": "full mail"
+            }"#,
+        )
+        .unwrap();
+        assert!(response.token_type.is_none());
+
+        let auth = UserAuth::from_auth_refresh_response(response);
+        assert_eq!(auth.uid.expose_secret(), &UserUid::from("uid"));
+        assert_eq!(auth.access_token.expose_secret(), "access");
+        assert_eq!(auth.refresh_token.expose_secret(), "refresh");
+    }
+}
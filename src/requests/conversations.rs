@@ -0,0 +1,65 @@
+use crate::domain::{Conversation, ConversationId, Message, PageFilter};
+use crate::http;
+use crate::http::RequestData;
+use crate::requests::page_filter_query;
+use serde::Deserialize;
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GetConversationsResponse {
+    pub conversations: Vec<Conversation>,
+}
+
+pub struct GetConversationsRequest<'a> {
+    filter: &'a PageFilter,
+}
+
+impl<'a> GetConversationsRequest<'a> {
+    pub fn new(filter: &'a PageFilter) -> Self {
+        Self { filter }
+    }
+}
+
+impl<'a> http::RequestDesc for GetConversationsRequest<'a> {
+    type Output = GetConversationsResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(
+            http::Method::Get,
+            format!("mail/v4/conversations?{}", page_filter_query(self.filter)),
+        )
+    }
+}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GetConversationResponse {
+    pub conversation: Conversation,
+    #[serde(default)]
+    pub messages: Vec<Message>,
+}
+
+pub struct GetConversationRequest<'a> {
+    id: &'a ConversationId,
+}
+
+impl<'a> GetConversationRequest<'a> {
+    pub fn new(id: &'a ConversationId) -> Self {
+        Self { id }
+    }
+}
+
+impl<'a> http::RequestDesc for GetConversationRequest<'a> {
+    type Output = GetConversationResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(
+            http::Method::Get,
+            format!("mail/v4/conversations/{}", self.id),
+        )
+    }
+}
@@ -1,9 +1,16 @@
 use crate::domain::{HumanVerification, HumanVerificationType};
+use crate::http::redact_body;
 use anyhow::anyhow;
 use serde::Deserialize;
+use std::time::Duration;
 use thiserror::Error;
 
 const HUMAN_VERIFICATION_REQUESTED: u32 = 9001;
+const APP_VERSION_BAD: u32 = 5003;
+const INSUFFICIENT_SCOPE: u32 = 9101;
+const INVALID_REFRESH_TOKEN: u32 = 10013;
+const DETAILS_DISPLAY_MAX_LEN: usize = 256;
+const RAW_BODY_MAX_LEN: usize = 512;
 
 #[derive(Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -14,7 +21,7 @@ pub struct APIErrorDesc {
 }
 
 /// Representation of the Proton API Error.
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub struct APIError {
     /// Http Code for the error.
     pub http_code: u16,
@@ -24,6 +31,14 @@ pub struct APIError {
     pub message: Option<String>,
     /// Optional JSON type with error details.
     pub details: Option<serde_json::Value>,
+    /// Correlation id of the failed request, include it when reporting issues to Proton.
+    pub request_id: Option<String>,
+    /// Delay requested by the server via the `Retry-After` header before retrying.
+    pub retry_after: Option<Duration>,
+    // A thin pointer, unlike `Box<str>`, keeps `http::Error` below clippy's `result_large_err`
+    // limit.
+    #[allow(clippy::box_collection)]
+    raw_body: Option<Box<String>>,
 }
 
 #[derive(Debug, Error)]
@@ -41,6 +56,40 @@ impl APIError {
         self.api_code == HUMAN_VERIFICATION_REQUESTED
     }
 
+    pub fn is_app_version_too_old(&self) -> bool {
+        self.api_code == APP_VERSION_BAD
+    }
+
+    /// The session lacks the scope needed for the request, e.g. it must be unlocked first with
+    /// [`Session::unlock`](crate::Session::unlock).
+    pub fn is_insufficient_scope(&self) -> bool {
+        self.api_code == INSUFFICIENT_SCOPE
+    }
+
+    /// The refresh token was revoked or expired, the session can't be refreshed anymore and a new
+    /// login is required.
+    pub fn is_invalid_refresh_token(&self) -> bool {
+        self.api_code == INVALID_REFRESH_TOKEN
+    }
+
+    /// Scope required for the request, if it was included in the error details.
+    pub fn required_scope(&self) -> Option<String> {
+        self.details
+            .as_ref()
+            .and_then(|d| d.get("Scope"))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+    }
+
+    /// Minimum app version required by the server, if it was included in the error details.
+    pub fn min_app_version(&self) -> Option<String> {
+        self.details
+            .as_ref()
+            .and_then(|d| d.get("MinVersion"))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+    }
+
     pub fn try_get_human_verification_details(
         &self,
     ) -> Result<HumanVerification, GetHumanVerificationError> {
@@ -70,7 +119,7 @@ impl APIError {
             let hv_type = match t.as_ref() {
                 "captcha" => HumanVerificationType::Captcha,
                 "email" => HumanVerificationType::Email,
-                "sms" => HumanVerificationType::Email,
+                "sms" => HumanVerificationType::Sms,
                 _ => {
                     return Err(GetHumanVerificationError::UnknownVerificationType(
                         t.clone(),
@@ -90,10 +139,29 @@ impl APIError {
 impl std::fmt::Display for APIError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(m) = &self.message {
-            m.fmt(f)
+            write!(f, "{m} (code={} http={})", self.api_code, self.http_code)?;
         } else {
-            write!(f, "APIError code={} http={}", self.api_code, self.http_code)
+            write!(f, "APIError code={} http={}", self.api_code, self.http_code)?;
+        }
+
+        if let Some(id) = &self.request_id {
+            write!(f, " request_id={id}")?;
         }
+
+        if let Some(details) = &self.details {
+            let details = redact_body(&details.to_string());
+            if details.len() > DETAILS_DISPLAY_MAX_LEN {
+                let mut end = DETAILS_DISPLAY_MAX_LEN;
+                while !details.is_char_boundary(end) {
+                    end -= 1;
+                }
+                write!(f, " details={}...", &details[..end])?;
+            } else {
+                write!(f, " details={details}")?;
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -104,9 +172,39 @@ impl APIError {
             api_code: 0,
             message: None,
             details: None,
+            request_id: None,
+            retry_after: None,
+            raw_body: None,
         }
     }
 
+    /// Start of the response body, with known secrets redacted, if it was not a valid API error.
+    /// Useful to tell errors of the proton API apart from those of proxies or the network edge,
+    /// which usually reply with HTML pages.
+    pub fn raw_body(&self) -> Option<&str> {
+        self.raw_body.as_deref().map(|b| b.as_str())
+    }
+
+    /// Whether the request which produced this error can be retried later: request timeouts
+    /// (408), rate limiting (429) and server errors (5xx).
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.http_code, 408 | 429 | 500..=599)
+    }
+
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    /// Set the retry delay from the value of the `Retry-After` header, only the delay in seconds
+    /// form is supported.
+    pub fn with_retry_after(mut self, header: Option<&str>) -> Self {
+        self.retry_after = header
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        self
+    }
+
     pub fn with_status_and_body(http_status: u16, body: &[u8]) -> Self {
         if body.is_empty() {
             return Self::new(http_status);
@@ -118,8 +216,71 @@ impl APIError {
                 api_code: e.code,
                 message: e.error,
                 details: e.details,
+                request_id: None,
+                retry_after: None,
+                raw_body: None,
             },
-            Err(_) => Self::new(http_status),
+            Err(_) => {
+                let mut end = body.len().min(RAW_BODY_MAX_LEN);
+                // Don't cut a multi byte character in half.
+                while end < body.len() && end > 0 && (body[end] & 0xC0) == 0x80 {
+                    end -= 1;
+                }
+                Self {
+                    raw_body: Some(redact_body(&String::from_utf8_lossy(&body[..end])).into()),
+                    ..Self::new(http_status)
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_error_with_status_and_body_keeps_details() {
+        let body =
+            br#"{"Code": 5003, "Error": "Update required", "Details": {"MinVersion": "1.2.0"}}"#;
+        let err = APIError::with_status_and_body(400, body);
+
+        assert_eq!(err.api_code, APP_VERSION_BAD);
+        assert_eq!(err.message.as_deref(), Some("Update required"));
+        assert_eq!(err.min_app_version().as_deref(), Some("1.2.0"));
+    }
+
+    #[test]
+    fn api_error_display_redacts_details() {
+        let body = br#"{"Code": 9001, "Error": "Human verification required", "Details": {"HumanVerificationMethods": ["captcha"], "HumanVerificationToken": "hv_token"}}"#;
+        let display = APIError::with_status_and_body(422, body).to_string();
+        assert!(display.contains("HumanVerificationMethods"), "{display}");
+        assert!(!display.contains("hv_token"), "{display}");
+    }
+
+    #[test]
+    fn api_error_keeps_raw_body_of_non_json_errors() {
+        let body = b"<html><body>502 Bad Gateway</body></html>";
+        let err = APIError::with_status_and_body(502, body);
+        assert_eq!(err.api_code, 0);
+        assert_eq!(
+            err.raw_body(),
+            Some("<html><body>502 Bad Gateway</body></html>")
+        );
+
+        let body = format!(
+            r#"{{"AccessToken": "secret", "x": "{}"}}"#,
+            "é".repeat(1000)
+        );
+        let err = APIError::with_status_and_body(503, body.as_bytes());
+        let raw = err.raw_body().unwrap();
+        assert!(raw.len() < body.len());
+        assert!(!raw.contains("secret"));
+        assert!(!raw.contains('\u{FFFD}'));
+
+        let body = br#"{"Code": 2001, "Error": "Invalid input"}"#;
+        assert!(APIError::with_status_and_body(422, body)
+            .raw_body()
+            .is_none());
+    }
+}
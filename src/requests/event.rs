@@ -1,18 +1,15 @@
 use crate::http;
 use crate::http::RequestData;
-use serde::Deserialize;
+use std::time::Duration;
 
-#[doc(hidden)]
-#[derive(Deserialize)]
-pub struct LatestEventResponse {
-    #[serde(rename = "EventID")]
-    pub event_id: crate::domain::EventId,
-}
+/// Extra time given to a long poll request on top of the requested wait time, so that the
+/// server has a chance to answer before the request times out locally.
+const LONG_POLL_TIMEOUT_MARGIN: Duration = Duration::from_secs(5);
 
 pub struct GetLatestEventRequest;
 
 impl http::RequestDesc for GetLatestEventRequest {
-    type Output = LatestEventResponse;
+    type Output = crate::domain::LatestEvent;
     type Response = http::JsonResponse<Self::Output>;
 
     fn build(&self) -> RequestData {
@@ -22,11 +19,21 @@ impl http::RequestDesc for GetLatestEventRequest {
 
 pub struct GetEventRequest<'a> {
     event_id: &'a crate::domain::EventId,
+    wait: Option<Duration>,
 }
 
 impl<'a> GetEventRequest<'a> {
     pub fn new(id: &'a crate::domain::EventId) -> Self {
-        Self { event_id: id }
+        Self {
+            event_id: id,
+            wait: None,
+        }
+    }
+
+    /// Ask the server to hold the request for up to `wait` until a new event is available.
+    pub fn with_wait(mut self, wait: Duration) -> Self {
+        self.wait = Some(wait);
+        self
     }
 }
 
@@ -35,9 +42,54 @@ impl<'a> http::RequestDesc for GetEventRequest<'a> {
     type Response = http::JsonResponse<Self::Output>;
 
     fn build(&self) -> RequestData {
+        let Some(wait) = self.wait else {
+            return RequestData::new(
+                http::Method::Get,
+                format!("core/v4/events/{}", self.event_id),
+            );
+        };
+
         RequestData::new(
             http::Method::Get,
-            format!("core/v4/events/{}", self.event_id),
+            format!("core/v4/events/{}?Wait={}", self.event_id, wait.as_secs()),
+        )
+        .timeout(wait + LONG_POLL_TIMEOUT_MARGIN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_event_request_with_wait() {
+        use http::RequestDesc;
+
+        let id = crate::domain::EventId("event_id".to_string());
+        let data = GetEventRequest::new(&id).build();
+        assert_eq!(data.url(), "core/v4/events/event_id");
+
+        let data = GetEventRequest::new(&id)
+            .with_wait(Duration::from_secs(30))
+            .build();
+        assert_eq!(data.url(), "core/v4/events/event_id?Wait=30");
+    }
+
+    #[test]
+    fn latest_event_response_flags() {
+        let response = serde_json::from_str::<crate::domain::LatestEvent>(
+            r#"{"Code": 1000, "EventID": "id"}"#,
+        )
+        .unwrap();
+        assert_eq!(response.event_id.0, "id");
+        assert_eq!(response.more, crate::domain::MoreEvents::No);
+        assert!(!response.needs_full_resync());
+
+        let response = serde_json::from_str::<crate::domain::LatestEvent>(
+            r#"{"Code": 1000, "EventID": "id", "More": 1, "Refresh": 1}"#,
         )
+        .unwrap();
+        assert_eq!(response.more, crate::domain::MoreEvents::Yes);
+        assert!(response.needs_full_resync());
     }
 }
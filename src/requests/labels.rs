@@ -1,7 +1,7 @@
-use crate::domain::{Label, LabelType};
+use crate::domain::{Label, LabelCount, LabelId, LabelType};
 use crate::http;
 use crate::http::RequestData;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 pub struct GetLabelsRequest {
     label_type: LabelType,
@@ -27,7 +27,139 @@ impl http::RequestDesc for GetLabelsRequest {
     fn build(&self) -> RequestData {
         RequestData::new(
             http::Method::Get,
-            format!("core/v4/labels?Type={}", self.label_type as u8),
+            format!("core/v4/labels?Type={}", u8::from(self.label_type)),
         )
     }
 }
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+pub struct LabelResponse {
+    #[serde(rename = "Label")]
+    pub label: Label,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreateLabelRequest<'a> {
+    name: &'a str,
+    color: &'a str,
+    #[serde(rename = "Type")]
+    label_type: u8,
+    #[serde(rename = "ParentID", skip_serializing_if = "Option::is_none")]
+    parent_id: Option<&'a str>,
+}
+
+impl<'a> CreateLabelRequest<'a> {
+    pub fn new(
+        name: &'a str,
+        color: &'a str,
+        label_type: LabelType,
+        parent_id: Option<&'a LabelId>,
+    ) -> Self {
+        Self {
+            name,
+            color,
+            label_type: label_type.into(),
+            parent_id: parent_id.map(|id| id.0.as_str()),
+        }
+    }
+}
+
+impl<'a> http::RequestDesc for CreateLabelRequest<'a> {
+    type Output = LabelResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Post, "core/v4/labels").json(self)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct UpdateLabelRequest<'a> {
+    #[serde(skip)]
+    id: &'a LabelId,
+    name: &'a str,
+    color: &'a str,
+    #[serde(rename = "ParentID", skip_serializing_if = "Option::is_none")]
+    parent_id: Option<&'a str>,
+}
+
+impl<'a> UpdateLabelRequest<'a> {
+    pub fn new(
+        id: &'a LabelId,
+        name: &'a str,
+        color: &'a str,
+        parent_id: Option<&'a LabelId>,
+    ) -> Self {
+        Self {
+            id,
+            name,
+            color,
+            parent_id: parent_id.map(|id| id.0.as_str()),
+        }
+    }
+}
+
+impl<'a> http::RequestDesc for UpdateLabelRequest<'a> {
+    type Output = LabelResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        // Proton replaces the label with the submitted fields, hence PUT rather than PATCH.
+        RequestData::new(http::Method::Put, format!("core/v4/labels/{}", self.id)).json(self)
+    }
+}
+
+pub struct DeleteLabelRequest<'a> {
+    id: &'a LabelId,
+}
+
+impl<'a> DeleteLabelRequest<'a> {
+    pub fn new(id: &'a LabelId) -> Self {
+        Self { id }
+    }
+}
+
+impl<'a> http::RequestDesc for DeleteLabelRequest<'a> {
+    type Output = ();
+    type Response = http::NoResponse;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Delete, format!("core/v4/labels/{}", self.id))
+    }
+}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GetMessageCountsResponse {
+    pub counts: Vec<LabelCount>,
+}
+
+pub struct GetMessageCountsRequest;
+
+impl http::RequestDesc for GetMessageCountsRequest {
+    type Output = GetMessageCountsResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Get, "mail/v4/messages/count")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delete_label_request_uses_labels_endpoint() {
+        use http::RequestDesc;
+
+        let id = LabelId("label_id".to_string());
+        let data = DeleteLabelRequest::new(&id).build();
+        assert_eq!(data.method(), http::Method::Delete);
+        assert_eq!(data.url(), "core/v4/labels/label_id");
+    }
+}
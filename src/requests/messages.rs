@@ -0,0 +1,215 @@
+use crate::domain::{DraftTemplate, Message, MessageFilter, MessageId, PageFilter};
+use crate::http;
+use crate::http::RequestData;
+use serde::{Deserialize, Serialize};
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MessageResponse {
+    pub message: Message,
+}
+
+pub struct GetMessageRequest<'a> {
+    id: &'a MessageId,
+}
+
+impl<'a> GetMessageRequest<'a> {
+    pub fn new(id: &'a MessageId) -> Self {
+        Self { id }
+    }
+}
+
+impl<'a> http::RequestDesc for GetMessageRequest<'a> {
+    type Output = MessageResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Get, format!("mail/v4/messages/{}", self.id))
+    }
+}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GetMessagesResponse {
+    #[serde(default)]
+    pub total: i64,
+    pub messages: Vec<Message>,
+}
+
+pub struct GetMessagesRequest<'a> {
+    filter: &'a MessageFilter,
+}
+
+impl<'a> GetMessagesRequest<'a> {
+    pub fn new(filter: &'a MessageFilter) -> Self {
+        Self { filter }
+    }
+}
+
+impl<'a> http::RequestDesc for GetMessagesRequest<'a> {
+    type Output = GetMessagesResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(
+            http::Method::Get,
+            format!("mail/v4/messages?{}", message_filter_query(self.filter)),
+        )
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreateDraftRequest<'a> {
+    message: &'a DraftTemplate,
+}
+
+impl<'a> CreateDraftRequest<'a> {
+    pub fn new(template: &'a DraftTemplate) -> Self {
+        Self { message: template }
+    }
+}
+
+impl<'a> http::RequestDesc for CreateDraftRequest<'a> {
+    type Output = MessageResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Post, "mail/v4/messages").json(self)
+    }
+}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SendMessageResponse {
+    pub sent: Message,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SendMessageRequest<'a> {
+    #[serde(skip)]
+    id: &'a MessageId,
+    packages: &'a serde_json::Value,
+}
+
+impl<'a> SendMessageRequest<'a> {
+    /// Send the draft `id`. The encrypted message `packages` are prepared by the caller.
+    pub fn new(id: &'a MessageId, packages: &'a serde_json::Value) -> Self {
+        Self { id, packages }
+    }
+}
+
+impl<'a> http::RequestDesc for SendMessageRequest<'a> {
+    type Output = SendMessageResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Post, format!("mail/v4/messages/{}", self.id)).json(self)
+    }
+}
+
+/// Query parameters for the listing endpoints.
+pub(crate) fn page_filter_query(filter: &PageFilter) -> String {
+    let mut query = format!("Page={}", filter.page);
+    if let Some(page_size) = filter.page_size {
+        query.push_str(&format!("&PageSize={page_size}"));
+    }
+    if let Some(label_id) = &filter.label_id {
+        query.push_str(&format!("&LabelID={}", encode_query_value(&label_id.0)));
+    }
+    query
+}
+
+fn message_filter_query(filter: &MessageFilter) -> String {
+    let mut query = page_filter_query(&filter.page);
+    let mut push = |key: &str, value: &str| {
+        query.push_str(&format!("&{key}={}", encode_query_value(value)));
+    };
+
+    if let Some(id) = &filter.conversation_id {
+        push("ConversationID", &id.to_string());
+    }
+    if let Some(from) = &filter.from {
+        push("From", from);
+    }
+    if let Some(to) = &filter.to {
+        push("To", to);
+    }
+    if let Some(keyword) = &filter.keyword {
+        push("Keyword", keyword);
+    }
+    if let Some(begin) = filter.begin {
+        push("Begin", &begin.to_string());
+    }
+    if let Some(end) = filter.end {
+        push("End", &end.to_string());
+    }
+    if let Some(unread) = filter.unread {
+        push("Unread", if unread { "1" } else { "0" });
+    }
+    if let Some(attachments) = filter.attachments {
+        push("Attachments", if attachments { "1" } else { "0" });
+    }
+    query
+}
+
+/// Percent encode everything but the unreserved characters of RFC 3986.
+fn encode_query_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_filter_query_only_includes_set_fields() {
+        let filter = MessageFilter::default();
+        assert_eq!(message_filter_query(&filter), "Page=0");
+
+        let filter = MessageFilter {
+            page: PageFilter {
+                label_id: None,
+                page: 1,
+                page_size: Some(50),
+            },
+            conversation_id: Some("c/1==".parse().unwrap()),
+            from: Some("foo+bar@baz.com".to_string()),
+            to: None,
+            keyword: Some("hello world & more".to_string()),
+            begin: Some(1_600_000_000),
+            end: Some(1_700_000_000),
+            unread: Some(true),
+            attachments: Some(false),
+        };
+        assert_eq!(
+            message_filter_query(&filter),
+            "Page=1&PageSize=50&ConversationID=c%2F1%3D%3D&From=foo%2Bbar%40baz.com\
+             &Keyword=hello%20world%20%26%20more&Begin=1600000000&End=1700000000&Unread=1\
+             &Attachments=0"
+        );
+    }
+
+    #[test]
+    fn page_filter_query_encodes_label_id() {
+        let filter = PageFilter {
+            label_id: Some("l+1/2==".parse().unwrap()),
+            page: 2,
+            page_size: None,
+        };
+        assert_eq!(page_filter_query(&filter), "Page=2&LabelID=l%2B1%2F2%3D%3D");
+    }
+}
@@ -1,15 +1,19 @@
 //! Representation of all the JSON data types that need to be submitted.
 
 mod auth;
+mod conversations;
 mod errors;
 mod event;
 mod labels;
+mod messages;
 mod tests;
 mod user;
 
 pub use auth::*;
+pub use conversations::*;
 pub use errors::*;
 pub use event::*;
 pub use labels::*;
+pub use messages::*;
 pub use tests::*;
 pub use user::*;
@@ -1,7 +1,7 @@
-use crate::domain::User;
+use crate::domain::{HumanVerificationType, KeySalt, User};
 use crate::http;
 use crate::http::{JsonResponse, RequestData};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -19,3 +19,83 @@ impl http::RequestDesc for UserInfoRequest {
         RequestData::new(http::Method::Get, "core/v4/users")
     }
 }
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct HumanVerificationCodeDestination<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phone: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct HumanVerificationCodeBody<'a> {
+    #[serde(rename = "Type")]
+    hv_type: &'a str,
+    destination: HumanVerificationCodeDestination<'a>,
+}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GetKeySaltsResponse {
+    pub key_salts: Vec<KeySalt>,
+}
+
+pub struct GetKeySaltsRequest {}
+
+impl http::RequestDesc for GetKeySaltsRequest {
+    type Output = GetKeySaltsResponse;
+    type Response = JsonResponse<Self::Output>;
+
+    fn build(&self) -> RequestData {
+        RequestData::new(http::Method::Get, "core/v4/keys/salts")
+    }
+}
+
+/// Request a human verification code to be sent via email or sms.
+pub struct RequestHumanVerificationCodeRequest<'a> {
+    hv_type: HumanVerificationType,
+    destination: &'a str,
+}
+
+impl<'a> RequestHumanVerificationCodeRequest<'a> {
+    pub fn new_email(address: &'a str) -> Self {
+        Self {
+            hv_type: HumanVerificationType::Email,
+            destination: address,
+        }
+    }
+
+    pub fn new_sms(phone: &'a str) -> Self {
+        Self {
+            hv_type: HumanVerificationType::Sms,
+            destination: phone,
+        }
+    }
+}
+
+impl<'a> http::RequestDesc for RequestHumanVerificationCodeRequest<'a> {
+    type Output = ();
+    type Response = http::NoResponse;
+
+    fn build(&self) -> RequestData {
+        let destination = match self.hv_type {
+            HumanVerificationType::Sms => HumanVerificationCodeDestination {
+                address: None,
+                phone: Some(self.destination),
+            },
+            _ => HumanVerificationCodeDestination {
+                address: Some(self.destination),
+                phone: None,
+            },
+        };
+
+        RequestData::new(http::Method::Post, "core/v4/users/code").json(HumanVerificationCodeBody {
+            hv_type: self.hv_type.as_str(),
+            destination,
+        })
+    }
+}
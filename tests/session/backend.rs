@@ -0,0 +1,742 @@
+use crate::utils::{lowercase_lines, serve_responses};
+use go_gpa_server::Server;
+use proton_api_rs::http::reqwest_client::ReqwestClient;
+use proton_api_rs::http::ureq_client::UReqClient;
+use proton_api_rs::http::{ClientAsync, ClientSync, RequestDesc, Sequence};
+use proton_api_rs::{http, ping};
+use std::net::TcpListener;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+#[test]
+fn client_from_ureq_agent() {
+    let server = Server::new().expect("failed to create test server");
+    let url = server.url().expect("Failed to get server url");
+
+    let client = UReqClient::from_agent(ureq::AgentBuilder::new().build(), &url, "Other");
+    ping().do_sync(&client).expect("Failed to ping");
+}
+
+#[tokio::test]
+async fn client_from_reqwest_client() {
+    let server = Server::new().expect("failed to create test server");
+    let url = server.url().expect("Failed to get server url");
+
+    let client = ReqwestClient::from_client(reqwest::Client::new(), &url);
+    ping().do_async(&client).await.expect("Failed to ping");
+}
+
+struct LocaleRequest;
+
+impl http::RequestDesc for LocaleRequest {
+    type Output = ();
+    type Response = http::NoResponse;
+
+    fn build(&self) -> http::RequestData {
+        http::RequestData::new(http::Method::Get, "tests/ping").header("X-Pm-Locale", "fr_FR")
+    }
+}
+
+/// Accept a single request and return its lower cased header lines.
+fn serve_once() -> (String, std::thread::JoinHandle<Vec<String>>) {
+    let (url, handle) = serve(1);
+    (
+        url,
+        std::thread::spawn(move || handle.join().unwrap().remove(0)),
+    )
+}
+
+/// Accept `count` requests, replying with [`MOCK_BODY`], and return their lower cased header
+/// lines.
+fn serve(count: usize) -> (String, std::thread::JoinHandle<Vec<Vec<String>>>) {
+    serve_with_headers(count, "")
+}
+
+/// Same as [`serve`], but adds `extra_headers` to every response.
+fn serve_with_headers(
+    count: usize,
+    extra_headers: &'static str,
+) -> (String, std::thread::JoinHandle<Vec<Vec<String>>>) {
+    let (url, handle) = serve_with_status(count, "200 OK", extra_headers);
+    (
+        url,
+        std::thread::spawn(move || lowercase_lines(handle.join().unwrap())),
+    )
+}
+
+/// Same as [`serve_with_headers`], but replies with `status` instead of `200 OK` and returns the
+/// header lines as they were received.
+fn serve_with_status(
+    count: usize,
+    status: &'static str,
+    extra_headers: &'static str,
+) -> (String, std::thread::JoinHandle<Vec<Vec<String>>>) {
+    serve_responses(vec![(status, MOCK_BODY); count], extra_headers)
+}
+
+const MOCK_BODY: &str = r#"{"Code":1000}"#;
+
+#[test]
+fn client_default_headers_ureq() {
+    let (url, handle) = serve_once();
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .default_header("X-Pm-Locale", "en_US")
+        .default_header("X-Custom", "custom")
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    LocaleRequest
+        .to_request()
+        .do_sync(&client)
+        .expect("Failed to send request");
+
+    let headers = handle.join().unwrap();
+    assert!(headers.contains(&"x-pm-locale: fr_fr".to_string()));
+    assert!(!headers.contains(&"x-pm-locale: en_us".to_string()));
+    assert!(headers.contains(&"x-custom: custom".to_string()));
+}
+
+#[tokio::test]
+async fn client_default_headers_reqwest() {
+    let (url, handle) = serve_once();
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .default_header("X-Pm-Locale", "en_US")
+        .default_header("X-Custom", "custom")
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+
+    LocaleRequest
+        .to_request()
+        .do_async(&client)
+        .await
+        .expect("Failed to send request");
+
+    let headers = handle.join().unwrap();
+    assert!(headers.contains(&"x-pm-locale: fr_fr".to_string()));
+    assert!(!headers.contains(&"x-pm-locale: en_us".to_string()));
+    assert!(headers.contains(&"x-custom: custom".to_string()));
+}
+
+struct AppVersionRequest;
+
+impl http::RequestDesc for AppVersionRequest {
+    type Output = ();
+    type Response = http::NoResponse;
+
+    fn build(&self) -> http::RequestData {
+        http::RequestData::new(http::Method::Get, "tests/ping")
+            .header("X-Pm-Appversion", "Override@1.0.0")
+    }
+}
+
+#[test]
+fn client_request_app_version_overrides_default_ureq() {
+    let (url, handle) = serve_once();
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .app_version("Default@0.1.0")
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    AppVersionRequest
+        .to_request()
+        .do_sync(&client)
+        .expect("Failed to send request");
+
+    let headers = handle.join().unwrap();
+    let app_versions = headers
+        .iter()
+        .filter(|h| h.starts_with("x-pm-appversion:"))
+        .collect::<Vec<_>>();
+    assert_eq!(app_versions, vec!["x-pm-appversion: override@1.0.0"]);
+}
+
+#[tokio::test]
+async fn client_request_app_version_overrides_default_reqwest() {
+    let (url, handle) = serve_once();
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .app_version("Default@0.1.0")
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+
+    AppVersionRequest
+        .to_request()
+        .do_async(&client)
+        .await
+        .expect("Failed to send request");
+
+    let headers = handle.join().unwrap();
+    let app_versions = headers
+        .iter()
+        .filter(|h| h.starts_with("x-pm-appversion:"))
+        .collect::<Vec<_>>();
+    assert_eq!(app_versions, vec!["x-pm-appversion: override@1.0.0"]);
+}
+
+#[tokio::test]
+async fn client_build_ref_both_backends() {
+    let server = Server::new().expect("failed to create test server");
+    let url = server.url().expect("Failed to get server url");
+
+    let builder = http::ClientBuilder::new().base_url(&url).allow_http();
+    let sync_client = builder
+        .build_ref::<UReqClient>()
+        .expect("Failed to build ureq client");
+    let async_client = builder
+        .build_ref::<ReqwestClient>()
+        .expect("Failed to build reqwest client");
+
+    ping().do_sync(&sync_client).expect("Failed to ping");
+    ping()
+        .do_async(&async_client)
+        .await
+        .expect("Failed to ping");
+}
+
+#[test]
+fn client_min_tls_version_1_3() {
+    let builder = http::ClientBuilder::new().min_tls_version(http::TlsVersion::Tls1_3);
+    builder
+        .build_ref::<UReqClient>()
+        .expect("Failed to build ureq client");
+    builder
+        .build_ref::<ReqwestClient>()
+        .expect("Failed to build reqwest client");
+}
+
+#[test]
+fn client_ureq_cancelled_request_is_not_sent() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind listener");
+    listener.set_nonblocking(true).unwrap();
+    let client = http::ClientBuilder::new()
+        .base_url(&format!("http://{}", listener.local_addr().unwrap()))
+        .allow_http()
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    let flag = Arc::new(AtomicBool::new(true));
+    let result = LocaleRequest
+        .compose()
+        .with(move |r| r.cancel_flag(flag.clone()))
+        .to_request()
+        .do_sync(&client);
+
+    match result {
+        Err(http::Error::Connection(e)) => assert!(e.to_string().contains("cancelled")),
+        r => panic!("Unexpected result: {r:?}"),
+    }
+    assert!(listener.accept().is_err());
+}
+
+/// Url on which nothing is listening.
+fn closed_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind listener");
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+#[test]
+fn client_alternative_routing_ureq() {
+    let (url, handle) = serve_once();
+    let client = http::ClientBuilder::new()
+        .base_url(&closed_url())
+        .alternative_routing(vec![url])
+        .allow_http()
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    LocaleRequest
+        .to_request()
+        .do_sync(&client)
+        .expect("Failed to send request");
+    handle.join().unwrap();
+}
+
+#[tokio::test]
+async fn client_alternative_routing_reqwest() {
+    let (url, handle) = serve_once();
+    let client = http::ClientBuilder::new()
+        .base_url(&closed_url())
+        .alternative_routing(vec![url])
+        .allow_http()
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+
+    LocaleRequest
+        .to_request()
+        .do_async(&client)
+        .await
+        .expect("Failed to send request");
+    handle.join().unwrap();
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CodeResponse {
+    code: u32,
+}
+
+struct CodeRequest;
+
+impl http::RequestDesc for CodeRequest {
+    type Output = CodeResponse;
+    type Response = http::JsonResponse<Self::Output>;
+
+    fn build(&self) -> http::RequestData {
+        http::RequestData::new(http::Method::Get, "tests/code")
+    }
+}
+
+#[test]
+fn client_boxed_request_queue() {
+    let (url, handle) = serve(2);
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    let queue: Vec<http::BoxedRequest> = vec![
+        http::BoxedRequest::new(http::RequestData::new(http::Method::Get, "tests/raw")),
+        http::BoxedRequest::from(CodeRequest).map(|r| Ok(r.code.to_string().into())),
+    ];
+
+    let results = queue
+        .into_iter()
+        .map(|r| r.do_sync(&client).expect("Failed to execute request"))
+        .collect::<Vec<_>>();
+    assert_eq!(results[0].as_ref(), MOCK_BODY.as_bytes());
+    assert_eq!(results[1].as_ref(), b"1000");
+
+    let requests = handle.join().unwrap();
+    assert!(requests[0][0].starts_with("get /tests/raw"));
+    assert!(requests[1][0].starts_with("get /tests/code"));
+}
+
+#[derive(Debug)]
+enum DomainError {
+    Offline,
+    Http(#[allow(dead_code)] http::Error),
+}
+
+impl From<http::Error> for DomainError {
+    fn from(value: http::Error) -> Self {
+        DomainError::Http(value)
+    }
+}
+
+fn to_domain_error(e: http::Error) -> DomainError {
+    match e {
+        http::Error::Connection(_) => DomainError::Offline,
+        e => DomainError::Http(e),
+    }
+}
+
+#[tokio::test]
+async fn client_sequence_map_err() {
+    let ureq_client = http::ClientBuilder::new()
+        .base_url(&closed_url())
+        .allow_http()
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+    let result = LocaleRequest
+        .to_request()
+        .map_err(to_domain_error)
+        .do_sync(&ureq_client);
+    assert!(matches!(result, Err(DomainError::Offline)), "{result:?}");
+
+    let reqwest_client = http::ClientBuilder::new()
+        .base_url(&closed_url())
+        .allow_http()
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+    let result = LocaleRequest
+        .to_request()
+        .map_err(to_domain_error)
+        .do_async(&reqwest_client)
+        .await;
+    assert!(matches!(result, Err(DomainError::Offline)), "{result:?}");
+}
+
+struct HeadRequest;
+
+impl http::RequestDesc for HeadRequest {
+    type Output = Option<u64>;
+    type Response = http::HeadResponse;
+
+    fn build(&self) -> http::RequestData {
+        http::RequestData::new(http::Method::Head, "tests/raw")
+    }
+}
+
+#[tokio::test]
+async fn client_head_request() {
+    let (url, handle) = serve(2);
+
+    let ureq_client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+    let length = HeadRequest
+        .to_request()
+        .do_sync(&ureq_client)
+        .expect("Failed to send request");
+    assert_eq!(length, Some(MOCK_BODY.len() as u64));
+
+    let reqwest_client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+    let length = HeadRequest
+        .to_request()
+        .do_async(&reqwest_client)
+        .await
+        .expect("Failed to send request");
+    assert_eq!(length, Some(MOCK_BODY.len() as u64));
+
+    let requests = handle.join().unwrap();
+    assert!(requests.iter().all(|r| r[0].starts_with("head /tests/raw")));
+}
+
+const SET_COOKIE: &str = "Set-Cookie: Session-Id=abc; Path=/\r\n";
+
+#[test]
+fn client_disable_cookies_ureq() {
+    let (url, handle) = serve_with_headers(2, SET_COOKIE);
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .disable_cookies()
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    for _ in 0..2 {
+        LocaleRequest
+            .to_request()
+            .do_sync(&client)
+            .expect("Failed to send request");
+    }
+
+    let requests = handle.join().unwrap();
+    assert!(!requests[1].iter().any(|h| h.starts_with("cookie:")));
+}
+
+#[tokio::test]
+async fn client_disable_cookies_reqwest() {
+    let (url, handle) = serve_with_headers(2, SET_COOKIE);
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .disable_cookies()
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+
+    for _ in 0..2 {
+        LocaleRequest
+            .to_request()
+            .do_async(&client)
+            .await
+            .expect("Failed to send request");
+    }
+
+    let requests = handle.join().unwrap();
+    assert!(!requests[1].iter().any(|h| h.starts_with("cookie:")));
+}
+
+#[test]
+fn client_cookies_enabled_by_default() {
+    let (url, handle) = serve_with_headers(2, SET_COOKIE);
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    for _ in 0..2 {
+        LocaleRequest
+            .to_request()
+            .do_sync(&client)
+            .expect("Failed to send request");
+    }
+
+    let requests = handle.join().unwrap();
+    assert!(requests[1].iter().any(|h| h == "cookie: session-id=abc"));
+}
+
+#[tokio::test]
+async fn client_sequence_timeout() {
+    // Connections are accepted by the OS, but no response is ever sent.
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind listener");
+    let client = http::ClientBuilder::new()
+        .base_url(&format!("http://{}", listener.local_addr().unwrap()))
+        .allow_http()
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+
+    let result = LocaleRequest
+        .to_request()
+        .timeout(std::time::Duration::from_millis(100))
+        .do_async(&client)
+        .await;
+    assert!(matches!(result, Err(http::Error::Timeout(_))), "{result:?}");
+}
+
+/// Async client whose requests fail once its delay elapsed and which sleeps on the tokio timer, so
+/// that [`Sequence::timeout`] is exercised without [`ReqwestClient`].
+#[derive(Clone)]
+struct SlowClient(std::time::Duration);
+
+struct SlowRequest;
+
+impl http::ClientRequest for SlowRequest {
+    fn header(self, _: impl AsRef<str>, _: impl AsRef<str>) -> Self {
+        self
+    }
+}
+
+impl http::ClientRequestBuilder for SlowClient {
+    type Request = SlowRequest;
+
+    fn new_request(&self, _: &http::RequestData) -> Self::Request {
+        SlowRequest
+    }
+}
+
+impl TryFrom<http::ClientBuilder> for SlowClient {
+    type Error = anyhow::Error;
+
+    fn try_from(_: http::ClientBuilder) -> Result<Self, Self::Error> {
+        Ok(Self(std::time::Duration::ZERO))
+    }
+}
+
+impl ClientAsync for SlowClient {
+    fn execute_async<R: http::FromResponse>(
+        &self,
+        _: Self::Request,
+    ) -> http::BoxFuture<'_, http::Result<R::Output>> {
+        Box::pin(async move {
+            tokio::time::sleep(self.0).await;
+            Err(http::Error::Other(anyhow::anyhow!("slow request")))
+        })
+    }
+
+    fn execute_blocking<F, R>(&self, f: F) -> http::BoxFuture<'_, http::Result<R>>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        Box::pin(async move { Ok(f()) })
+    }
+
+    fn sleep(&self, duration: std::time::Duration) -> http::BoxFuture<'_, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+#[tokio::test]
+async fn client_sequence_timeout_custom_client() {
+    use std::time::Duration;
+
+    let client = SlowClient(Duration::from_secs(10));
+    let result = LocaleRequest
+        .to_request()
+        .timeout(Duration::from_millis(100))
+        .do_async(&client)
+        .await;
+    assert!(matches!(result, Err(http::Error::Timeout(_))), "{result:?}");
+
+    // Requests which complete in time are not cut short by the timer.
+    let client = SlowClient(Duration::from_millis(10));
+    let result = LocaleRequest
+        .to_request()
+        .timeout(Duration::from_secs(10))
+        .do_async(&client)
+        .await;
+    assert!(matches!(result, Err(http::Error::Other(_))), "{result:?}");
+}
+
+const REDIRECT: &str = "Location: http://127.0.0.1:1/elsewhere\r\n";
+
+#[test]
+fn client_redirect_policy_none_ureq() {
+    let (url, handle) = serve_with_status(1, "302 Found", REDIRECT);
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .redirect_policy(http::RedirectPolicy::None)
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    let result = LocaleRequest.to_request().do_sync(&client);
+    handle.join().unwrap();
+    match result {
+        Err(http::Error::Redirect(location, _)) => {
+            assert_eq!(location, "http://127.0.0.1:1/elsewhere")
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn client_redirect_policy_none_reqwest() {
+    let (url, handle) = serve_with_status(1, "302 Found", REDIRECT);
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .redirect_policy(http::RedirectPolicy::None)
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+
+    let result = LocaleRequest.to_request().do_async(&client).await;
+    handle.join().unwrap();
+    match result {
+        Err(http::Error::Redirect(location, _)) => {
+            assert_eq!(location, "http://127.0.0.1:1/elsewhere")
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+}
+
+/// Error bodies are bound by the max body size as well, exceeding it only keeps the status.
+fn assert_error_without_body(result: Result<(), http::Error>) {
+    match result {
+        Err(http::Error::API(e)) => {
+            assert_eq!(e.http_code, 422);
+            assert_eq!(e.api_code, 0);
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn client_error_body_exceeds_max_body_size_ureq() {
+    let (url, handle) = serve_with_status(1, "422 Unprocessable Entity", "");
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .max_body_size(4)
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    let result = LocaleRequest.to_request().do_sync(&client);
+    handle.join().unwrap();
+    assert_error_without_body(result);
+}
+
+#[tokio::test]
+async fn client_error_body_exceeds_max_body_size_reqwest() {
+    let (url, handle) = serve_with_status(1, "422 Unprocessable Entity", "");
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .max_body_size(4)
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+
+    let result = LocaleRequest.to_request().do_async(&client).await;
+    handle.join().unwrap();
+    assert_error_without_body(result);
+}
+
+struct PmUidRequest;
+
+impl http::RequestDesc for PmUidRequest {
+    type Output = ();
+    type Response = http::NoResponse;
+
+    fn build(&self) -> http::RequestData {
+        http::RequestData::new(http::Method::Get, "tests/ping")
+            .pm_uid(&proton_api_rs::domain::UserUid::from("uid"))
+    }
+}
+
+#[test]
+fn client_header_casing_ureq() {
+    let (url, handle) = serve_with_status(1, "200 OK", "");
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    PmUidRequest
+        .to_request()
+        .do_sync(&client)
+        .expect("Failed to send request");
+
+    let headers = handle.join().unwrap().remove(0);
+    assert!(
+        headers.contains(&"X-Pm-Uid: uid".to_string()),
+        "{headers:?}"
+    );
+}
+
+#[tokio::test]
+async fn client_header_casing_reqwest() {
+    let (url, handle) = serve_with_status(1, "200 OK", "");
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+
+    PmUidRequest
+        .to_request()
+        .do_async(&client)
+        .await
+        .expect("Failed to send request");
+
+    let headers = handle.join().unwrap().remove(0);
+    assert!(
+        headers.contains(&"X-Pm-Uid: uid".to_string()),
+        "{headers:?}"
+    );
+}
+
+#[test]
+fn client_execute_raw_ureq() {
+    let (url, handle) = serve_once();
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<UReqClient>()
+        .expect("Failed to build client");
+
+    let body = client
+        .execute_raw(LocaleRequest.build())
+        .expect("Failed to send request");
+    assert_eq!(body.as_ref(), MOCK_BODY.as_bytes());
+
+    let headers = handle.join().unwrap();
+    assert!(headers[0].starts_with("get /tests/ping"));
+    assert!(headers.contains(&"x-pm-locale: fr_fr".to_string()));
+}
+
+#[tokio::test]
+async fn client_execute_raw_reqwest() {
+    let (url, handle) = serve_once();
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<ReqwestClient>()
+        .expect("Failed to build client");
+
+    let body = client
+        .execute_raw_async(LocaleRequest.build())
+        .await
+        .expect("Failed to send request");
+    assert_eq!(body.as_ref(), MOCK_BODY.as_bytes());
+
+    let headers = handle.join().unwrap();
+    assert!(headers[0].starts_with("get /tests/ping"));
+    assert!(headers.contains(&"x-pm-locale: fr_fr".to_string()));
+}
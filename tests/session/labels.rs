@@ -1,7 +1,8 @@
 use crate::utils::{
-    create_session_and_server, ClientSync, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD,
+    create_session_and_server, login_default_user, ClientSync, DEFAULT_USER_EMAIL,
+    DEFAULT_USER_PASSWORD,
 };
-use proton_api_rs::domain::LabelType;
+use proton_api_rs::domain::{LabelId, LabelType};
 use proton_api_rs::http::Sequence;
 use proton_api_rs::{Session, SessionType};
 use secrecy::Secret;
@@ -15,11 +16,21 @@ fn session_label_fetch() {
         .expect("failed to create default user");
 
     let folder_id = server
-        .create_label(&user_id, "my_folder", None, LabelType::Folder as i32)
+        .create_label(
+            &user_id,
+            "my_folder",
+            None,
+            u8::from(LabelType::Folder) as i32,
+        )
         .expect("Failed to create folder");
 
     let label_id = server
-        .create_label(&user_id, "my_label", None, LabelType::Label as i32)
+        .create_label(
+            &user_id,
+            "my_label",
+            None,
+            u8::from(LabelType::Label) as i32,
+        )
         .expect("Failed to create folder");
 
     let auth_result = Session::login(
@@ -54,3 +65,57 @@ fn session_label_fetch() {
         }
     }
 }
+
+#[test]
+fn session_label_create_and_update() {
+    let (client, server) = create_session_and_server::<ClientSync>();
+
+    let (_, s) = login_default_user(&client, &server);
+
+    let folder = s
+        .create_label("my_folder", "#7272a7", LabelType::Folder, None)
+        .do_sync(&client)
+        .expect("Failed to create folder");
+    assert_eq!("my_folder", folder.name);
+    assert_eq!(LabelType::Folder, folder.label_type);
+
+    let folder = s
+        .update_label(&folder.id, "renamed_folder", "#cf5858", None)
+        .do_sync(&client)
+        .expect("Failed to update folder");
+    assert_eq!("renamed_folder", folder.name);
+    assert_eq!("#cf5858", folder.color);
+
+    let folders = s
+        .get_labels(LabelType::Folder)
+        .do_sync(&client)
+        .expect("Failed to get folder labels");
+    assert_eq!(1, folders.len());
+    assert_eq!(folder.id, folders[0].id);
+    assert_eq!("renamed_folder", folders[0].name);
+}
+
+#[test]
+fn session_label_delete() {
+    let (client, server) = create_session_and_server::<ClientSync>();
+    let (user_id, s) = login_default_user(&client, &server);
+
+    let label_id = server
+        .create_label(
+            &user_id,
+            "my_label",
+            None,
+            u8::from(LabelType::Label) as i32,
+        )
+        .expect("Failed to create label");
+
+    s.delete_label(&LabelId(label_id.as_ref().to_string()))
+        .do_sync(&client)
+        .expect("Failed to delete label");
+
+    let labels = s
+        .get_labels(LabelType::Label)
+        .do_sync(&client)
+        .expect("Failed to get labels");
+    assert!(labels.is_empty());
+}
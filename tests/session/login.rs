@@ -1,10 +1,14 @@
 use crate::utils::{
-    create_session_and_server, ClientASync, ClientSync, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD,
+    create_session_and_server, create_session_and_server_with, login_default_user,
+    login_default_user_async, lowercase_lines, serve_responses, ClientASync, ClientSync,
+    DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD,
 };
 use proton_api_rs::domain::SecretString;
-use proton_api_rs::http::Sequence;
-use proton_api_rs::{http, LoginError, Session, SessionType};
+use proton_api_rs::http::{Method, Metrics, Sequence};
+use proton_api_rs::{http, LoginError, ScopedSession, Session, SessionType};
 use secrecy::{ExposeSecret, Secret};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use tokio;
 
 #[test]
@@ -25,13 +29,43 @@ fn session_login() {
     assert!(matches!(auth_result, SessionType::Authenticated(_)));
 
     if let SessionType::Authenticated(s) = auth_result {
+        let session_user_id = s.user_id().expect("Login should provide the user id");
         let user = s.get_user().do_sync(&client).expect("Failed to get user");
         assert_eq!(user.id.as_ref(), user_id.as_ref());
+        assert_eq!(session_user_id, user.id);
 
         s.logout().do_sync(&client).expect("Failed to logout")
     }
 }
 
+#[test]
+fn session_from_tokens() {
+    let (client, server) = create_session_and_server::<ClientSync>();
+
+    let (user_id, session) = login_default_user(&client, &server);
+
+    // The stale access token is only noticed by the first request, which refreshes the session.
+    let rs = session.get_refresh_data();
+    let restored = Session::from_tokens(
+        rs.user_uid.expose_secret().clone(),
+        SecretString::new("stale".to_string()),
+        rs.token.clone(),
+    );
+    assert!(restored.user_id().is_none());
+
+    let user = restored
+        .get_user()
+        .do_sync(&client)
+        .expect("Failed to get user");
+    assert_eq!(user.id.as_ref(), user_id.as_ref());
+    assert_eq!(restored.user_id(), Some(user.id));
+
+    restored
+        .logout()
+        .do_sync(&client)
+        .expect("Failed to logout")
+}
+
 #[test]
 fn session_login_auto_refresh() {
     let (client, server) = create_session_and_server::<ClientSync>();
@@ -111,6 +145,108 @@ async fn session_login_async() {
     }
 }
 
+#[cfg(feature = "async-send")]
+#[tokio::test(flavor = "multi_thread")]
+async fn session_login_async_spawned() {
+    let (client, server) = create_session_and_server::<ClientASync>();
+
+    let (user_id, _) = server
+        .create_user(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD)
+        .expect("failed to create default user");
+
+    let user = tokio::spawn(async move {
+        let auth_result = Session::login(
+            DEFAULT_USER_EMAIL,
+            &Secret::<String>::new(DEFAULT_USER_PASSWORD.to_string()),
+            None,
+        )
+        .do_async(&client)
+        .await
+        .expect("Failed to login");
+
+        let SessionType::Authenticated(s) = auth_result else {
+            panic!("Expected authenticated session");
+        };
+
+        s.get_user()
+            .do_async(&client)
+            .await
+            .expect("Failed to get user")
+    })
+    .await
+    .expect("Failed to join task");
+
+    assert_eq!(user.id.as_ref(), user_id.as_ref());
+}
+
+#[test]
+fn session_login_all() {
+    let (client, server) = create_session_and_server::<ClientSync>();
+
+    let (user_id, _) = server
+        .create_user(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD)
+        .expect("failed to create default user");
+    let session = Session::login_all(
+        DEFAULT_USER_EMAIL,
+        &Secret::<String>::new(DEFAULT_USER_PASSWORD.to_string()),
+        || panic!("TOTP should not be requested"),
+        None,
+    )
+    .do_sync(&client)
+    .expect("Failed to login");
+
+    let user = session
+        .get_user()
+        .do_sync(&client)
+        .expect("Failed to get user");
+    assert_eq!(user.id.as_ref(), user_id.as_ref());
+
+    session.logout().do_sync(&client).expect("Failed to logout")
+}
+
+#[tokio::test]
+async fn session_login_all_async() {
+    let (client, server) = create_session_and_server::<ClientASync>();
+
+    let (user_id, _) = server
+        .create_user(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD)
+        .expect("failed to create default user");
+    let session = Session::login_all(
+        DEFAULT_USER_EMAIL,
+        &Secret::<String>::new(DEFAULT_USER_PASSWORD.to_string()),
+        || panic!("TOTP should not be requested"),
+        None,
+    )
+    .do_async(&client)
+    .await
+    .expect("Failed to login");
+
+    let user = session
+        .get_user()
+        .do_async(&client)
+        .await
+        .expect("Failed to get user");
+    assert_eq!(user.id.as_ref(), user_id.as_ref());
+
+    session
+        .logout()
+        .do_async(&client)
+        .await
+        .expect("Failed to logout")
+}
+
+#[tokio::test]
+async fn session_async_client_requires_allow_http() {
+    let (_, server) = create_session_and_server::<ClientASync>();
+    let url = server.url().expect("Failed to get server url");
+
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .build::<ClientASync>()
+        .expect("Failed to create client");
+    assert!(proton_api_rs::ping().do_async(&client).await.is_err());
+}
+
 #[test]
 fn session_login_invalid_user() {
     let (client, _server) = create_session_and_server::<ClientSync>();
@@ -126,3 +262,289 @@ fn session_login_invalid_user() {
         Err(LoginError::Request(http::Error::API(_)))
     ));
 }
+
+#[test]
+fn session_login_invalid_user_request_id() {
+    let (client, _server) =
+        create_session_and_server_with::<ClientSync>(|b| b.generate_request_ids());
+    let auth_result = Session::login(
+        "bar",
+        &SecretString::new(DEFAULT_USER_PASSWORD.into()),
+        None,
+    )
+    .do_sync(&client);
+
+    let Err(LoginError::Request(http::Error::API(e))) = auth_result else {
+        panic!("Expected API error");
+    };
+    assert!(e.request_id.is_some());
+}
+
+#[test]
+fn scoped_session_logout_on_drop() {
+    let (client, server) = create_session_and_server::<ClientSync>();
+
+    let (_, session) = login_default_user(&client, &server);
+
+    let scoped = ScopedSession::new_sync(session.clone(), client.clone());
+    scoped
+        .get_user()
+        .do_sync(&client)
+        .expect("Failed to get user");
+    drop(scoped);
+
+    assert!(session.get_user().do_sync(&client).is_err());
+}
+
+#[test]
+fn session_refresh_now() {
+    let (client, server) = create_session_and_server::<ClientSync>();
+
+    let (_, session) = login_default_user(&client, &server);
+
+    let rs = session.get_refresh_data();
+    session
+        .refresh_now()
+        .do_sync(&client)
+        .expect("Failed to refresh");
+    let rs_post_refresh = session.get_refresh_data();
+
+    assert_ne!(
+        rs.token.expose_secret(),
+        rs_post_refresh.token.expose_secret()
+    );
+
+    session
+        .get_user()
+        .do_sync(&client)
+        .expect("Failed to get user");
+    session.logout().do_sync(&client).expect("Failed to logout")
+}
+
+#[test]
+fn session_response_exceeds_max_body_size() {
+    let (client, server) = create_session_and_server_with::<ClientSync>(|b| b.max_body_size(16));
+
+    server
+        .create_user(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD)
+        .expect("failed to create default user");
+    let auth_result = Session::login(
+        DEFAULT_USER_EMAIL,
+        &Secret::<String>::new(DEFAULT_USER_PASSWORD.to_string()),
+        None,
+    )
+    .do_sync(&client);
+
+    assert!(matches!(
+        auth_result,
+        Err(LoginError::Request(http::Error::Request(_)))
+    ));
+}
+
+#[test]
+fn session_clone_shares_refresh() {
+    let (client, server) = create_session_and_server::<ClientSync>();
+
+    let (_, session) = login_default_user(&client, &server);
+
+    let clone = session.clone();
+    clone
+        .refresh_now()
+        .do_sync(&client)
+        .expect("Failed to refresh");
+
+    assert_eq!(
+        session.get_refresh_data().token.expose_secret(),
+        clone.get_refresh_data().token.expose_secret()
+    );
+    session
+        .get_user()
+        .do_sync(&client)
+        .expect("Failed to get user");
+}
+
+/// Counts the requests to the user info endpoint.
+#[derive(Default)]
+struct UserRequestCounter(AtomicUsize);
+
+impl Metrics for UserRequestCounter {
+    fn on_request_start(&self, _: Method, url: &str) {
+        if url == "core/v4/users" {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[test]
+fn session_cached_user_invalidated_on_logout() {
+    let counter = Arc::new(UserRequestCounter::default());
+    let (client, server) =
+        create_session_and_server_with::<ClientSync>(|b| b.with_metrics(counter.clone()));
+
+    let (user_id, session) = login_default_user(&client, &server);
+    let requests_after_login = counter.0.load(Ordering::SeqCst);
+
+    let ttl = std::time::Duration::from_secs(60);
+    let user = session
+        .cached_user(ttl)
+        .do_sync(&client)
+        .expect("Failed to get user");
+    assert_eq!(user.id.as_ref(), user_id.as_ref());
+    assert_eq!(counter.0.load(Ordering::SeqCst), requests_after_login + 1);
+
+    // Served from the cache, without another request.
+    let cached = session
+        .cached_user(ttl)
+        .do_sync(&client)
+        .expect("Failed to get cached user");
+    assert_eq!(cached.id.as_ref(), user_id.as_ref());
+    assert_eq!(counter.0.load(Ordering::SeqCst), requests_after_login + 1);
+
+    session.logout().do_sync(&client).expect("Failed to logout");
+    assert!(session.cached_user(ttl).do_sync(&client).is_err());
+}
+
+#[test]
+fn session_unlock() {
+    let (client, server) = create_session_and_server::<ClientSync>();
+
+    let (_, session) = login_default_user(&client, &server);
+    let password = Secret::<String>::new(DEFAULT_USER_PASSWORD.to_string());
+
+    let rs = session.get_refresh_data();
+    let unlocked = session
+        .unlock(&password)
+        .do_sync(&client)
+        .expect("Failed to unlock");
+    assert!(matches!(unlocked, SessionType::Authenticated(_)));
+
+    // The original session observes the new authentication.
+    let rs_post_unlock = session.get_refresh_data();
+    assert_ne!(
+        rs.token.expose_secret(),
+        rs_post_unlock.token.expose_secret()
+    );
+
+    session
+        .get_user()
+        .do_sync(&client)
+        .expect("Failed to get user");
+
+    let wrong_password = Secret::<String>::new("wrong".to_string());
+    assert!(session.unlock(&wrong_password).do_sync(&client).is_err());
+
+    session.logout().do_sync(&client).expect("Failed to logout")
+}
+
+#[test]
+fn session_shorthands_sync() {
+    let (client, server) = create_session_and_server::<ClientSync>();
+
+    let (user_id, session) = login_default_user(&client, &server);
+
+    let user = session.get_user_sync(&client).expect("Failed to get user");
+    assert_eq!(user.id.as_ref(), user_id.as_ref());
+
+    let event_id = session
+        .get_latest_event_sync(&client)
+        .expect("Failed to get latest event");
+    session
+        .get_event_sync(&client, &event_id)
+        .expect("Failed to get event");
+
+    session.logout_sync(&client).expect("Failed to logout")
+}
+
+#[tokio::test]
+async fn session_shorthands_async() {
+    let (client, server) = create_session_and_server::<ClientASync>();
+
+    let (user_id, session) = login_default_user_async(&client, &server).await;
+
+    let user = session
+        .get_user_async(&client)
+        .await
+        .expect("Failed to get user");
+    assert_eq!(user.id.as_ref(), user_id.as_ref());
+
+    let event_id = session
+        .get_latest_event_async(&client)
+        .await
+        .expect("Failed to get latest event");
+    session
+        .get_event_async(&client, &event_id)
+        .await
+        .expect("Failed to get event");
+
+    session
+        .logout_async(&client)
+        .await
+        .expect("Failed to logout")
+}
+
+/// Stops the login after the auth info request, which is all the mock server can answer.
+const AUTH_INFO_ERROR: (&str, &str) = (
+    "422 Unprocessable Entity",
+    r#"{"Code": 2001, "Error": "Invalid input"}"#,
+);
+
+#[test]
+fn session_login_uses_unauth_session() {
+    let (url, handle) = serve_responses(
+        vec![
+            (
+                "200 OK",
+                r#"{"Code": 1000, "UID": "unauth_uid", "AccessToken": "unauth_token", "RefreshToken": "unauth_refresh"}"#,
+            ),
+            AUTH_INFO_ERROR,
+        ],
+        "",
+    );
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<ClientSync>()
+        .expect("Failed to build client");
+
+    let password = SecretString::new(DEFAULT_USER_PASSWORD.to_string());
+    assert!(Session::login(DEFAULT_USER_EMAIL, &password, None)
+        .do_sync(&client)
+        .is_err());
+
+    let requests = lowercase_lines(handle.join().unwrap());
+    assert!(requests[0][0].starts_with("post /auth/v4/sessions "));
+    assert!(requests[1][0].starts_with("post /auth/v4/info "));
+    assert!(requests[1].contains(&"x-pm-uid: unauth_uid".to_string()));
+    assert!(requests[1].contains(&"authorization: bearer unauth_token".to_string()));
+}
+
+#[test]
+fn session_login_without_unauth_session_endpoint() {
+    let (url, handle) = serve_responses(
+        vec![
+            ("404 Not Found", r#"{"Code": 404, "Error": "Not found"}"#),
+            AUTH_INFO_ERROR,
+        ],
+        "",
+    );
+    let client = http::ClientBuilder::new()
+        .base_url(&url)
+        .allow_http()
+        .build::<ClientSync>()
+        .expect("Failed to build client");
+
+    let password = SecretString::new(DEFAULT_USER_PASSWORD.to_string());
+    let err = Session::login(DEFAULT_USER_EMAIL, &password, None)
+        .do_sync(&client)
+        .expect_err("The mock server does not complete the login");
+    assert!(
+        matches!(&err, LoginError::Request(http::Error::API(e)) if e.http_code == 422),
+        "{err:?}"
+    );
+
+    // The login proceeds without the unauthenticated session.
+    let requests = lowercase_lines(handle.join().unwrap());
+    assert!(requests[1][0].starts_with("post /auth/v4/info "));
+    assert!(!requests[1].iter().any(|l| l.starts_with("x-pm-uid:")));
+    assert!(!requests[1].iter().any(|l| l.starts_with("authorization:")));
+}
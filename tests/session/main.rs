@@ -1,3 +1,6 @@
+mod backend;
 mod labels;
 mod login;
+mod metrics;
+mod proxy;
 mod utils;
@@ -0,0 +1,56 @@
+use crate::utils::{create_session_and_server_with, ClientASync, ClientSync};
+use proton_api_rs::http::{Error, Method, Metrics, Sequence};
+use proton_api_rs::ping;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Default)]
+struct CountingMetrics {
+    started: AtomicUsize,
+    succeeded: AtomicUsize,
+}
+
+impl Metrics for CountingMetrics {
+    fn on_request_start(&self, method: Method, url: &str) {
+        assert_eq!(method, Method::Get);
+        assert_eq!(url, "tests/ping");
+        self.started.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_request_end(
+        &self,
+        _: Method,
+        _: &str,
+        _: Duration,
+        result: std::result::Result<(), &Error>,
+    ) {
+        if result.is_ok() {
+            self.succeeded.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[test]
+fn metrics_are_reported() {
+    let metrics = Arc::new(CountingMetrics::default());
+    let (client, _server) =
+        create_session_and_server_with::<ClientSync>(|b| b.with_metrics(metrics.clone()));
+
+    ping().do_sync(&client).expect("Failed to ping");
+
+    assert_eq!(metrics.started.load(Ordering::SeqCst), 1);
+    assert_eq!(metrics.succeeded.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn metrics_are_reported_async() {
+    let metrics = Arc::new(CountingMetrics::default());
+    let (client, _server) =
+        create_session_and_server_with::<ClientASync>(|b| b.with_metrics(metrics.clone()));
+
+    ping().do_async(&client).await.expect("Failed to ping");
+
+    assert_eq!(metrics.started.load(Ordering::SeqCst), 1);
+    assert_eq!(metrics.succeeded.load(Ordering::SeqCst), 1);
+}
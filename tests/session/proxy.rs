@@ -0,0 +1,49 @@
+use crate::utils::{create_session_and_server_with, serve_responses, ClientASync, ClientSync};
+use proton_api_rs::http::{Proxy, ProxyProtocol, Sequence};
+use proton_api_rs::{http, ping};
+
+fn bogus_proxy() -> Proxy {
+    Proxy {
+        protocol: ProxyProtocol::Socks5,
+        auth: None,
+        url: "bogus.proxy.invalid".to_string(),
+        port: 1080,
+    }
+}
+
+#[test]
+fn no_proxy_bypasses_proxy_for_localhost() {
+    let (client, _server) = create_session_and_server_with::<ClientSync>(|b| {
+        b.with_proxy(bogus_proxy())
+            .no_proxy(vec!["localhost".to_string(), "127.0.0.1".to_string()])
+    });
+
+    ping().do_sync(&client).expect("Failed to ping");
+}
+
+#[tokio::test]
+async fn no_proxy_bypasses_proxy_for_localhost_async() {
+    let (client, _server) = create_session_and_server_with::<ClientASync>(|b| {
+        b.with_proxy(bogus_proxy())
+            .no_proxy(vec!["localhost".to_string(), "127.0.0.1".to_string()])
+    });
+
+    ping().do_async(&client).await.expect("Failed to ping");
+}
+
+#[test]
+fn no_proxy_applies_to_alternative_urls() {
+    let (url, handle) = serve_responses(vec![("200 OK", r#"{"Code":1000}"#)], "");
+    // The base url can only be reached through the bogus proxy, the alternative one bypasses it.
+    let client = http::ClientBuilder::new()
+        .base_url("http://proxied.invalid")
+        .alternative_routing(vec![url])
+        .allow_http()
+        .with_proxy(bogus_proxy())
+        .no_proxy(vec!["127.0.0.1".to_string()])
+        .build::<ClientSync>()
+        .expect("Failed to build client");
+
+    ping().do_sync(&client).expect("Failed to ping");
+    handle.join().unwrap();
+}
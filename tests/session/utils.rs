@@ -1,7 +1,10 @@
-use go_gpa_server::Server;
+use go_gpa_server::{Server, UserId};
 use log::LevelFilter;
-use proton_api_rs::http;
-use proton_api_rs::http::ClientBuilder;
+use proton_api_rs::http::{ClientBuilder, Sequence};
+use proton_api_rs::{http, Session};
+use secrecy::Secret;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
 use std::sync::OnceLock;
 
 pub type ClientSync = http::ureq_client::UReqClient;
@@ -13,6 +16,14 @@ pub const DEFAULT_USER_EMAIL: &str = "foo@bar.com";
 pub const DEFAULT_USER_PASSWORD: &str = "12345";
 
 pub fn create_session_and_server<Client: TryFrom<ClientBuilder, Error = anyhow::Error> + Clone>(
+) -> (Client, Server) {
+    create_session_and_server_with(|b| b)
+}
+
+pub fn create_session_and_server_with<
+    Client: TryFrom<ClientBuilder, Error = anyhow::Error> + Clone,
+>(
+    f: impl FnOnce(ClientBuilder) -> ClientBuilder,
 ) -> (Client, Server) {
     let debug = if let Ok(v) = std::env::var("RUST_LOG") {
         if v.eq_ignore_ascii_case("debug") {
@@ -38,6 +49,102 @@ pub fn create_session_and_server<Client: TryFrom<ClientBuilder, Error = anyhow::
         client = client.debug()
     }
 
-    let client = client.build::<Client>().expect("Failed to create client");
+    let client = f(client)
+        .build::<Client>()
+        .expect("Failed to create client");
     (client, server)
 }
+
+/// Create the default user on `server` and log it in, returning its id and the session.
+pub fn login_default_user(client: &ClientSync, server: &Server) -> (UserId, Session) {
+    let (user_id, _) = server
+        .create_user(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD)
+        .expect("failed to create default user");
+    let session = Session::login_all(
+        DEFAULT_USER_EMAIL,
+        &Secret::<String>::new(DEFAULT_USER_PASSWORD.to_string()),
+        || panic!("TOTP should not be requested"),
+        None,
+    )
+    .do_sync(client)
+    .expect("Failed to login");
+    (user_id, session)
+}
+
+/// Same as [`login_default_user`] with the async client.
+pub async fn login_default_user_async(client: &ClientASync, server: &Server) -> (UserId, Session) {
+    let (user_id, _) = server
+        .create_user(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD)
+        .expect("failed to create default user");
+    let session = Session::login_all(
+        DEFAULT_USER_EMAIL,
+        &Secret::<String>::new(DEFAULT_USER_PASSWORD.to_string()),
+        || panic!("TOTP should not be requested"),
+        None,
+    )
+    .do_async(client)
+    .await
+    .expect("Failed to login");
+    (user_id, session)
+}
+
+/// Reply to each request with the next `(status, body)` of `responses`, adding `extra_headers` to
+/// every response, and return the request and header lines of every request as received.
+pub fn serve_responses(
+    responses: Vec<(&'static str, &'static str)>,
+    extra_headers: &'static str,
+) -> (String, std::thread::JoinHandle<Vec<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind listener");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = std::thread::spawn(move || {
+        responses
+            .into_iter()
+            .map(|(status, body)| {
+                let (mut stream, _) = listener.accept().expect("Failed to accept connection");
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut lines = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).expect("Failed to read request");
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    lines.push(line.trim().to_string());
+                }
+
+                // Drain the request body, closing the connection with unread data resets it.
+                let length = lines
+                    .iter()
+                    .find_map(|l| {
+                        let (name, value) = l.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse().expect("Invalid content length"))
+                    })
+                    .unwrap_or(0);
+                let mut request_body = vec![0; length];
+                reader
+                    .read_exact(&mut request_body)
+                    .expect("Failed to read request body");
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{extra_headers}Connection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .expect("Failed to write response");
+                lines
+            })
+            .collect()
+    });
+
+    (url, handle)
+}
+
+/// Lower case the lines returned by [`serve_responses`], header names are case insensitive.
+pub fn lowercase_lines(requests: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    requests
+        .into_iter()
+        .map(|lines| lines.iter().map(|l| l.to_lowercase()).collect())
+        .collect()
+}